@@ -0,0 +1,35 @@
+use std::env::current_dir;
+use std::fs::create_dir_all;
+
+use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+
+use secret_dice_contract_pathway::msg::{
+    BankrollResponse, BetResponse, CallbackMsg, ContractStatusResponse, ExecuteAnswer, ExecuteMsg, FeesResponse,
+    GameHistoryResponse, GameStatusResponse, InstantiateMsg, MigrateMsg, MyBalanceResponse,
+    MyGamesResponse, QueryMsg, ReceiveMsg, WinnerResponse,
+};
+
+/// Writes the JSON schemas of the contract's messages and responses to `schema/`
+fn main() {
+    let mut out_dir = current_dir().unwrap();
+    out_dir.push("schema");
+    create_dir_all(&out_dir).unwrap();
+    remove_schemas(&out_dir).unwrap();
+
+    export_schema(&schema_for!(InstantiateMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteAnswer), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
+    export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(CallbackMsg), &out_dir);
+    export_schema(&schema_for!(WinnerResponse), &out_dir);
+    export_schema(&schema_for!(GameStatusResponse), &out_dir);
+    export_schema(&schema_for!(GameHistoryResponse), &out_dir);
+    export_schema(&schema_for!(MyGamesResponse), &out_dir);
+    export_schema(&schema_for!(MyBalanceResponse), &out_dir);
+    export_schema(&schema_for!(ContractStatusResponse), &out_dir);
+    export_schema(&schema_for!(FeesResponse), &out_dir);
+    export_schema(&schema_for!(BankrollResponse), &out_dir);
+    export_schema(&schema_for!(BetResponse), &out_dir);
+}
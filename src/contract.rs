@@ -1,44 +1,296 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Deps, DepsMut, Env, Uint128, Coin,
-    MessageInfo, QueryResponse, Response, CosmosMsg, BankMsg,
-    StdError, StdResult
+    to_binary, from_binary, Addr, Binary, Deps, DepsMut, Env, Uint128, Coin,
+    MessageInfo, QueryResponse, Reply, Response, CosmosMsg, BankMsg, SubMsg, WasmMsg, WasmQuery,
+    ContractInfoResponse, StdError, StdResult, Storage,
 };
 
-use rand::{RngCore, SeedableRng};
+use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
+use secret_toolkit::permit::{validate, Permit, RevokedPermits, TokenPermissions};
+use secret_toolkit::snip20::{register_receive_msg, transfer_msg};
+use secret_toolkit::viewing_key::{ViewingKey, ViewingKeyStore};
 use sha2::{Digest, Sha256};
 
 use crate::error::{ContractError};
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, WinnerResponse};
+use crate::random::{commitment as secret_commitment, derive_bet_seed, derive_seed, roll_die};
+use crate::msg::{
+    BankrollResponse, BetResponse, CallbackMsg, ContractStatusResponse, ExecuteAnswer, ExecuteMsg, FeesResponse, GameHistoryResponse, GameStatusResponse,
+    InstantiateMsg, MigrateMsg, MyBalanceResponse, MyGamesResponse, PlayerResponse, Prediction, PublicGameRecord, QueryMsg, QueryWithPermit, ReceiveMsg,
+    WinnerResponse,
+};
 use crate::state::{
-    config, config_read, block_height, block_height_read,
-    ContractState, DiceRoller, Winner, State,
+    config, config_read, Config, games, games_read, game_key, block_height, block_height_read,
+    ContractState, Dice, DiceRoller, Expiration, Timeout, Winner, State,
+    append_history, history_read, history_count_read, player_history_read,
+    player_history_count_read, GameRecord, PlayerRecord, player_games, player_games_read,
+    PREFIX_REVOKED_PERMITS, ContractStatus, add_open_game, remove_open_game, open_games_read,
+    open_game_count_read, treasury, treasury_read,
+    bankroll, bankroll_read, house_seed, house_seed_read, contract_version, contract_version_read,
+    ContractVersion, Callback, Bet, BetOutcome, bets, bets_read, bet_count, bet_count_read,
 };
+use crate::helpers::padded;
+use crate::legacy::{legacy_block_height, legacy_block_height_read, legacy_state_read, LegacyContractState};
+
+/// the name and version recorded in storage, so that migrations know what they upgrade from
+const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// the game of the original single-game contract is carried over under this id
+pub const LEGACY_GAME_ID: u64 = 0;
+
+/// default and maximum page sizes of history queries
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+/// the most dice a game can be played with, and the most sides they can have
+const MAX_DICE: u8 = 10;
+const MAX_SIDES: u8 = 100;
+
+/// fees are expressed in basis points, i.e. hundredths of a percent
+const MAX_FEE_BPS: u16 = 10_000;
+
+/// by default a single bet can win at most 10% of the bankroll
+const DEFAULT_MAX_EXPOSURE_BPS: u16 = 1_000;
+
+/// the id of the submessages notifying callback contracts, whose failures are ignored
+const CALLBACK_REPLY_ID: u64 = 1;
+/// a callback running out of gas fails on its own instead of using up the gas of the dice roll
+const CALLBACK_GAS_LIMIT: u64 = 200_000;
 
+/// pad handle responses and log attributes to blocks of 256 bytes to prevent leaking info based on
+/// response size
+pub const BLOCK_SIZE: usize = 256;
 
 //////////////////////////////////////////////////////////////////////
 //////////////////////////////// Init ////////////////////////////////
 //////////////////////////////////////////////////////////////////////
 
-#[cfg_attr(not(feature = "library"), entry_point)]
+#[cfg_attr(not(feature = "library"), cosmwasm_std::entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
 ) -> StdResult<Response> {
+    if msg.stake.amount.is_zero() {
+        return Err(StdError::generic_err("The stake must be greater than zero."));
+    }
 
-    let state = State::default();
-    config(deps.storage).save(&state)?;
+    let fee_bps = msg.fee_bps.unwrap_or_default();
+    let house_edge_bps = msg.house_edge_bps.unwrap_or_default();
+    // a fee of 100% would leave nothing to pay the winner, and empty payouts are rejected by the bank
+    if fee_bps >= MAX_FEE_BPS || house_edge_bps >= MAX_FEE_BPS {
+        return Err(StdError::generic_err("The fee must be less than 10000 basis points."));
+    }
 
-    Ok(Response::default())
+    let max_exposure_bps = msg.max_exposure_bps.unwrap_or(DEFAULT_MAX_EXPOSURE_BPS);
+    if max_exposure_bps > MAX_FEE_BPS {
+        return Err(StdError::generic_err("The max exposure can't be more than 10000 basis points."));
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+
+    // when playing with a SNIP-20 token, register with it so that we get notified of deposits
+    if let Some(token) = &msg.token {
+        deps.api.addr_validate(token.address.as_str())?;
+        if msg.stake.denom != token.address.as_str() {
+            return Err(StdError::generic_err(
+                "The stake denom must be the token contract address."
+            ));
+        }
+
+        messages.push(register_receive_msg(
+            env.contract.code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            token.code_hash.clone(),
+            token.address.to_string(),
+        )?);
+    }
+
+    // a timeout too long to be represented would make every game fail to start
+    let reveal_timeout = msg.commit_reveal.as_ref().map(|commit_reveal| Timeout::Blocks(commit_reveal.reveal_timeout));
+    for timeout in [&msg.join_timeout, &msg.roll_timeout, &reveal_timeout].into_iter().flatten() {
+        timeout.after(&env.block)?;
+    }
+
+    let takes_bets = msg.commit_reveal.is_none();
+    seed_prngs(deps.storage, &env, &info.sender, msg.prng_seed.as_ref(), takes_bets)?;
+
+    let admin = match &msg.admin {
+        Some(admin) => deps.api.addr_validate(admin)?,
+        None => info.sender,
+    };
+
+    // games are created on demand when their first player joins,
+    // so only the table settings need to be stored here
+    let config_state = Config {
+        stake: msg.stake,
+        token: msg.token,
+        commit_reveal: msg.commit_reveal,
+        join_timeout: msg.join_timeout,
+        roll_timeout: msg.roll_timeout,
+        admin,
+        status: ContractStatus::Normal,
+        fee_bps,
+        house_edge_bps,
+        max_exposure_bps,
+    };
+    config(deps.storage).save(&config_state)?;
+    contract_version(deps.storage).save(&ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: CONTRACT_VERSION.to_string(),
+    })?;
+
+    Ok(Response::new()
+        .add_messages(messages))
+}
+
+/// Seeds the viewing key generator and the house seed with the creator's entropy, and the block's
+/// on chains that provide it. Everything else that goes into them is public, and a house seed
+/// anyone can compute would let bettors pick a winning secret, so a contract taking bets needs one
+/// of the two
+fn seed_prngs(
+    storage: &mut dyn Storage,
+    env: &Env,
+    creator: &Addr,
+    entropy: Option<&Binary>,
+    takes_bets: bool,
+) -> StdResult<()> {
+    if takes_bets && entropy.is_none() && block_random(env).is_none() {
+        return Err(StdError::generic_err("A prng_seed is needed to take bets."));
+    }
+
+    let mut prng_seed: Vec<u8> = env.block.height.to_be_bytes().to_vec();
+    prng_seed.extend(env.block.time.nanos().to_be_bytes());
+    prng_seed.extend(creator.as_bytes());
+    for entropy in [entropy, block_random(env)].into_iter().flatten() {
+        prng_seed.extend(entropy.as_slice());
+    }
+    ViewingKey::set_seed(storage, &Sha256::digest(&prng_seed));
+    prng_seed.extend(b"house");
+    house_seed(storage).save(&Sha256::digest(&prng_seed).to_vec())
+}
+
+//////////////////////////////////////////////////////////////////////
+/////////////////////////////// Migrate //////////////////////////////
+//////////////////////////////////////////////////////////////////////
+
+#[cfg_attr(not(feature = "library"), cosmwasm_std::entry_point)]
+pub fn migrate(
+    mut deps: DepsMut,
+    env: Env,
+    msg: MigrateMsg,
+) -> StdResult<Response> {
+    match contract_version_read(deps.storage).may_load()? {
+        Some(stored) if stored.contract != CONTRACT_NAME => {
+            return Err(StdError::generic_err(format!("Can't migrate from {}.", stored.contract)));
+        },
+        Some(stored) if version_parts(&stored.version) > version_parts(CONTRACT_VERSION) => {
+            return Err(StdError::generic_err(format!("Can't migrate back from version {}.", stored.version)));
+        },
+        Some(_) => {},
+        // only the original single-game contract has no version record
+        None => migrate_legacy_state(deps.branch(), &env, msg)?,
+    }
+
+    contract_version(deps.storage).save(&ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: CONTRACT_VERSION.to_string(),
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("version", CONTRACT_VERSION))
+}
+
+/// The numbers of a `major.minor.patch` version, so that versions compare in order
+fn version_parts(version: &str) -> Vec<u64> {
+    version.split(['.', '-']).map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// Replaces the single game stored in place of the config with the settings the original contract
+/// had built in, 1 SCRT games of 2 players with a d6, and carries the game over as `LEGACY_GAME_ID`.
+/// A finished game is also added to the history
+fn migrate_legacy_state(
+    deps: DepsMut,
+    env: &Env,
+    msg: MigrateMsg,
+) -> StdResult<()> {
+    let legacy = legacy_state_read(deps.storage)
+        .load()
+        .map_err(|_| StdError::generic_err("No single-game state to migrate."))?;
+    let admin = msg.admin
+        .ok_or_else(|| StdError::generic_err("Migrating the single-game contract needs an admin."))?;
+    let admin = deps.api.addr_validate(&admin)?;
+
+    let stake = Coin::new(1_000_000, "uscrt");
+
+    // the single-game contract had no commit-reveal mode, so the migrated one takes bets
+    seed_prngs(deps.storage, env, &admin, msg.prng_seed.as_ref(), true)?;
+    config(deps.storage).save(&Config {
+        stake: stake.clone(),
+        token: None,
+        commit_reveal: None,
+        join_timeout: None,
+        roll_timeout: None,
+        admin,
+        status: ContractStatus::Normal,
+        fee_bps: 0,
+        house_edge_bps: 0,
+        max_exposure_bps: DEFAULT_MAX_EXPOSURE_BPS,
+    })?;
+
+    let players: Vec<DiceRoller> = [legacy.player_1, legacy.player_2]
+        .into_iter()
+        .flatten()
+        .map(|player| DiceRoller::new(player.name, player.addr, player.secret))
+        .collect();
+    let mut state = State { seats: 2, ..State::default() };
+    let legacy_height = legacy_block_height_read(deps.storage).may_load()?;
+    legacy_block_height(deps.storage).remove();
+
+    match legacy.state {
+        LegacyContractState::Init => return Ok(()),
+        LegacyContractState::Got1 | LegacyContractState::Got2 => {
+            // the deposits are still held by the contract
+            for player in &players {
+                track_deposit(deps.storage, player.addr(), LEGACY_GAME_ID)?;
+            }
+            state.state = if players.len() == 2 { ContractState::Full } else { ContractState::Open };
+        },
+        LegacyContractState::Done => {
+            let dice_roll = legacy.dice_roll
+                .ok_or_else(|| StdError::generic_err("The finished game has no dice roll."))?;
+            state.state = ContractState::Done;
+            state.dice_faces = vec![dice_roll];
+            state.dice_roll = Some(dice_roll as u16);
+            state.winner = legacy.winner.map(|winner| Winner::new(winner.name, winner.addr));
+
+            if let Some(height) = legacy_height {
+                block_height(deps.storage).save(&game_key(LEGACY_GAME_ID), &height)?;
+            }
+        },
+    }
+
+    state.players = players;
+    games(deps.storage).save(&game_key(LEGACY_GAME_ID), &state)?;
+
+    if state.state == ContractState::Done {
+        // the history records the block the game ended in when it is known, but not its time
+        let mut ended = env.clone();
+        ended.block.height = legacy_height.unwrap_or(env.block.height);
+        archive_game(deps.storage, &ended, LEGACY_GAME_ID, &state, &stake)?;
+    }
+
+    Ok(())
 }
 
 //////////////////////////////////////////////////////////////////////
 //////////////////////////// Execute /////////////////////////////////
 //////////////////////////////////////////////////////////////////////
 
-#[cfg_attr(not(feature = "library"), entry_point)]
+#[cfg_attr(not(feature = "library"), cosmwasm_std::entry_point)]
 pub fn execute(
     deps: DepsMut,
     env: Env,
@@ -46,40 +298,206 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Join { name, secret } => try_join(deps, info, name, secret),
-        ExecuteMsg::RollDice {} => try_roll_dice(deps, env, info),
-        ExecuteMsg::Leave {} => try_leave(deps, info),
+        ExecuteMsg::Join { game_id, name, seats, dice, secret, commitment, callback } => {
+            try_join(deps, env, info, game_id, name, seats, dice, secret, commitment, callback)
+        },
+        ExecuteMsg::Bet { prediction, dice, secret } => try_bet(deps, env, info, prediction, dice, secret),
+        ExecuteMsg::SettleBet { bet_id } => try_settle_bet(deps, env, info, bet_id),
+        ExecuteMsg::Reveal { game_id, secret, salt } => try_reveal(deps, info, game_id, secret, salt),
+        ExecuteMsg::RollDice { game_id } => try_roll_dice(deps, env, info, game_id),
+        ExecuteMsg::Leave { game_id } => try_leave(deps, info, game_id),
+        ExecuteMsg::Cancel { game_id } => try_cancel(deps, info, game_id),
+        ExecuteMsg::ClaimTimeout { game_id } => try_claim_timeout(deps, env, info, game_id),
+        ExecuteMsg::CreateViewingKey { entropy } => try_create_viewing_key(deps, env, info, entropy),
+        ExecuteMsg::SetViewingKey { key } => try_set_viewing_key(deps, info, key),
+        ExecuteMsg::RevokePermit { permit_name } => try_revoke_permit(deps, info, permit_name),
+        ExecuteMsg::ChangeAdmin { address } => try_change_admin(deps, info, address),
+        ExecuteMsg::SetStatus { status } => try_set_status(deps, info, status),
+        ExecuteMsg::EmergencyRefund { limit } => try_emergency_refund(deps, info, limit),
+        ExecuteMsg::WithdrawFees { recipient, amount } => {
+            try_withdraw_fees(deps, info, recipient, amount)
+        },
+        ExecuteMsg::DepositBankroll {} => try_deposit_bankroll(deps, info),
+        ExecuteMsg::WithdrawBankroll { recipient, amount } => {
+            try_withdraw_bankroll(deps, info, recipient, amount)
+        },
+        ExecuteMsg::SetMaxExposure { max_exposure_bps } => {
+            try_set_max_exposure(deps, info, max_exposure_bps)
+        },
+        ExecuteMsg::Receive { from, amount, msg, .. } => {
+            try_receive(deps, env, info, from, amount, msg)
+        },
+    }
+}
+
+/// Builds the message that pays `amount` of the stake's currency to `recipient`
+fn payout_msg(
+    config_state: &Config,
+    recipient: &Addr,
+    amount: Uint128,
+) -> StdResult<CosmosMsg> {
+    match &config_state.token {
+        Some(token) => transfer_msg(
+            recipient.to_string(),
+            amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            token.code_hash.clone(),
+            token.address.to_string(),
+        ),
+        None => Ok(CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin {
+                denom: config_state.stake.denom.clone(),
+                amount,
+            }],
+        })),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn try_join(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    game_id: u64,
     name: String,
-    secret: Uint128,
+    seats: Option<u8>,
+    dice: Option<Dice>,
+    secret: Option<Uint128>,
+    commitment: Option<Binary>,
+    callback: Option<Callback>,
 ) -> Result<Response, ContractError> {
-    let mut state = config(deps.storage).load()?;
+    let config_state = config_read(deps.storage).load()?;
+    deposit_funds(&info, &config_state.stake)?;
 
-    // player 1 joins, sends a secret and deposits 1 SCRT to the contract
-    // player 1's secret is stored privately
-    //
-    // player 2 joins, sends a secret and deposits 1 SCRT to the contract
-    // player 2's secret is stored privately
+    let player = new_player(&config_state, name, info.sender, secret, commitment)?;
+    join_game(deps, env, &config_state, game_id, seats, dice, callback, player)
+}
+
+/// Handles deposits made with the SNIP-20 token `Send` function
+pub fn try_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    from: Addr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
+    let config_state = config_read(deps.storage).load()?;
+
+    // only the whitelisted token contract can notify us of a deposit
+    match &config_state.token {
+        Some(token) if token.address == info.sender => {},
+        _ => return Err(ContractError::UnsupportedToken),
+    }
+
+    // games and bets are played with exactly the stake
+    let pay_stake = || if amount == config_state.stake.amount {
+        Ok(())
+    } else {
+        Err(ContractError::MustDepositStakeToPlay(config_state.stake.clone()))
+    };
+
+    let msg = msg.ok_or_else(|| StdError::generic_err("Missing receive message."))?;
+    match from_binary(&msg)? {
+        ReceiveMsg::Join { game_id, name, seats, dice, secret, commitment, callback } => {
+            pay_stake()?;
+            let player = new_player(&config_state, name, from, secret, commitment)?;
+            join_game(deps, env, &config_state, game_id, seats, dice, callback, player)
+        },
+        ReceiveMsg::Bet { prediction, dice, secret } => {
+            pay_stake()?;
+            place_bet(deps, env, &config_state, from, prediction, dice, secret)
+        },
+        ReceiveMsg::DepositBankroll {} => deposit_bankroll(deps.storage, &config_state, &from, amount),
+    }
+}
+
+/// Creates a player from their join message. Depending on the contract's mode, players join
+/// with either their secret or a commitment to it
+fn new_player(
+    config_state: &Config,
+    name: String,
+    addr: Addr,
+    secret: Option<Uint128>,
+    commitment: Option<Binary>,
+) -> Result<DiceRoller, ContractError> {
+    if config_state.commit_reveal.is_none() {
+        let secret = secret.ok_or(ContractError::MissingSecret)?;
+        return Ok(DiceRoller::new(name, addr, secret));
+    }
+
+    // sending the secret on a chain without encrypted inputs would make it public
+    let commitment = match (secret, commitment) {
+        (None, Some(commitment)) => commitment,
+        _ => return Err(ContractError::MissingCommitment),
+    };
+    if commitment.len() != 32 {
+        return Err(StdError::generic_err("The commitment must be a sha256 hash.").into());
+    }
+
+    Ok(DiceRoller::new_committed(name, addr, commitment))
+}
+
+/// Seats `player` in the game once their deposit has been checked. The first player creates the
+/// game and chooses how many `seats` it has, which `dice` it is played with and its `callback`
+#[allow(clippy::too_many_arguments)]
+fn join_game(
+    deps: DepsMut,
+    env: Env,
+    config_state: &Config,
+    game_id: u64,
+    seats: Option<u8>,
+    dice: Option<Dice>,
+    callback: Option<Callback>,
+    player: DiceRoller,
+) -> Result<Response, ContractError> {
+    if config_state.status != ContractStatus::Normal {
+        return Err(ContractError::JoinsAreStopped);
+    }
+
+    // a game that doesn't exist yet is created by its first player
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    // each player joins, sends a secret and deposits the stake to the contract
+    // the secrets are stored privately
+
+    let player_addr = player.addr().clone();
 
     // Check the state of the game
     match state.state {
         ContractState::Init => {
-            deposit_funds(&info)?;
-            state.player_1 = Some(DiceRoller::new(name, info.sender, secret));
-            state.state = ContractState::Got1;
+            let dice = dice.unwrap_or_default();
+            check_dice(&dice)?;
+
+            let seats = seats.unwrap_or(2);
+            if !(2..=dice.max_seats()).contains(&seats) {
+                return Err(ContractError::InvalidSeats(dice.max_seats()));
+            }
+            if let Some(callback) = &callback {
+                deps.api.addr_validate(callback.contract_addr.as_str())?;
+            }
+            state.seats = seats;
+            state.dice = dice;
+            state.callback = callback;
+            state.state = ContractState::Open;
+            state.deadline = config_state.join_timeout.as_ref().map(|timeout| timeout.after(&env.block)).transpose()?;
         },
-        ContractState::Got1 => {
-            deposit_funds(&info)?;
-            state.player_2 = Some(DiceRoller::new(name, info.sender, secret));
-            state.state = ContractState::Got2;
+        ContractState::Open => {
+            if state.is_player(&player_addr) {
+                return Err(ContractError::AlreadyJoined);
+            }
+            // they would otherwise be ignored
+            if seats.is_some() || dice.is_some() || callback.is_some() {
+                return Err(ContractError::OnlyCreatorChoosesSettings);
+            }
         },
-        ContractState::Got2 => {
-            // We already have both players
+        ContractState::Full => {
+            // All the seats are taken
             return Err(ContractError::GameIsFull);
         },
         ContractState::Done => {
@@ -88,525 +506,3537 @@ pub fn try_join(
         },
     }
 
-    config(deps.storage).save(&state)?;
+    state.players.push(player);
+
+    // the last seat is taken, the dice can be rolled
+    if state.players.len() == state.seats as usize {
+        state.state = ContractState::Full;
+        state.deadline = config_state.roll_timeout.as_ref().map(|timeout| timeout.after(&env.block)).transpose()?;
+
+        // in commit-reveal mode, the players now have a limited time to reveal
+        if let Some(commit_reveal) = &config_state.commit_reveal {
+            state.reveal_deadline = Some(Timeout::Blocks(commit_reveal.reveal_timeout).after(&env.block)?);
+        }
+    }
+
+    games(deps.storage).save(&game_key(game_id), &state)?;
+    track_deposit(deps.storage, &player_addr, game_id)?;
 
     Ok(Response::new()
-        .add_attribute("action", "join"))
+        .add_attribute("action", "join")
+        .add_attribute("game_id", game_id.to_string()))
+}
+
+fn check_dice(dice: &Dice) -> Result<(), ContractError> {
+    if !(1..=MAX_DICE).contains(&dice.count) || !(2..=MAX_SIDES).contains(&dice.sides) {
+        return Err(ContractError::InvalidDice { count: MAX_DICE, sides: MAX_SIDES });
+    }
+
+    Ok(())
 }
 
 fn deposit_funds(
     info: &MessageInfo,
+    stake: &Coin,
 ) -> Result<Response, ContractError> {
 
-    let amount = Uint128::new(1_000_000 /* 1mn uscrt = 1 SCRT */);
     if info.funds.len() != 1
-        || info.funds[0].amount != amount
-        || info.funds[0].denom != *"uscrt"
+        || info.funds[0].amount != stake.amount
+        || info.funds[0].denom != stake.denom
     {
-        return Err(ContractError::MustDepositScrtToPlay);
+        return Err(ContractError::MustDepositStakeToPlay(stake.clone()));
     }
 
     Ok(Response::default())
 }
 
-pub fn try_roll_dice(
+pub fn try_reveal(
     deps: DepsMut,
-    env: Env,
     info: MessageInfo,
+    game_id: u64,
+    secret: Uint128,
+    salt: Binary,
 ) -> Result<Response, ContractError> {
-    let mut state = config(deps.storage).load()?;
-
-    // once player 2 joins, we can derive a shared secret that no one knows
-    // then we can roll the dice and choose a winner
-    // dice roll 1-3: player 1 wins / dice roll 4-6: player 2 wins
-    //
-    // the winner then gets 2 SCRT
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
 
-    let dice_roll: u8;
-    let mut messages: Vec<CosmosMsg> = vec![];
+    if config_read(deps.storage).load()?.commit_reveal.is_none() {
+        return Err(ContractError::NotACommitRevealGame);
+    }
 
-    // Check the state of the game
+    // revealing before the other players committed would let them pick a winning secret
     match state.state {
-        ContractState::Init => {
+        ContractState::Init | ContractState::Open => {
             return Err(ContractError::StillWaitingForPlayers);
         },
-        ContractState::Got1 => {
-            return Err(ContractError::StillWaitingForPlayers);
+        ContractState::Done => {
+            return Err(ContractError::GameIsAlreadyOver);
         },
-        ContractState::Got2 => {
-            // get players
-            let player_1 = if let Some(player_1) = &state.player_1 {
-              player_1
-            } else { return Err(ContractError::StillWaitingForPlayers) };
-
-            let player_2 = if let Some(player_2) = &state.player_2 {
-              player_2
-            } else { return Err(ContractError::StillWaitingForPlayers) };
-
-            // validate players
-            if player_1.addr() != &info.sender && player_2.addr() != &info.sender {
-                return Err(ContractError::YouAreNotAPlayer);
-            }
+        ContractState::Full => {},
+    }
 
-            // saving the block height so that the winner cannpt be queried in the same block
-            block_height(deps.storage).save(&env.block.height)?;
+    let player = state.players
+        .iter_mut()
+        .find(|player| player.addr() == &info.sender)
+        .ok_or(ContractError::YouAreNotAPlayer)?;
 
-            let mut combined_secret: Vec<u8> = player_1.secret().to_be_bytes().to_vec();
-            combined_secret.extend(&player_2.secret().to_be_bytes());
-            combined_secret.extend(env.block.time.to_string().as_bytes());
+    let commitment = player.commitment().ok_or(ContractError::AlreadyRevealed)?;
+    if commitment.as_slice() != secret_commitment(&secret, &salt) {
+        return Err(ContractError::CommitmentMismatch);
+    }
+    player.reveal(secret);
 
-            let random_seed: [u8;32] = Sha256::digest(&combined_secret).into();
-            let mut rng = ChaChaRng::from_seed(random_seed);
+    games(deps.storage).save(&game_key(game_id), &state)?;
 
-            dice_roll = ((rng.next_u32() % 6) + 1) as u8;   // a number between 1 and 6
-            state.dice_roll = Some(dice_roll);
+    Ok(Response::new()
+        .add_attribute("action", "reveal")
+        .add_attribute("game_id", game_id.to_string()))
+}
 
-            let winner: Winner = if (1..=3).contains(&dice_roll) {
-                Winner::new(
-                    player_1.name().to_string(),
-                    player_1.addr().clone()
-                )
-            } else {
-                Winner::new(
-                    player_2.name().to_string(),
-                    player_2.addr().clone()
-                )
-            };
-            println!("dice roll = {}", dice_roll);
-            println!("winner is {}", winner.name());
+pub fn try_roll_dice(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    game_id: u64,
+) -> Result<Response, ContractError> {
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
 
-            state.winner = Some(winner.clone());
-            state.state = ContractState::Done;
+    // once all the seats are taken, we can derive a shared secret that no one knows
+    // then we can roll the dice and choose a winner
+    // every seat gets the same share of the dice faces, e.g. with 2 players
+    // dice roll 1-3: player 1 wins / dice roll 4-6: player 2 wins
+    //
+    // the winner then gets all the deposits
 
-            // Winner takes all!
-            messages.push(CosmosMsg::Bank(BankMsg::Send {
-                to_address: winner.addr().to_string(),
-                amount: vec![Coin::new(2_000_000, "uscrt")],
-            }));
+    let config_state = config_read(deps.storage).load()?;
+    if config_state.status == ContractStatus::StopAll {
+        return Err(ContractError::ContractIsStopped);
+    }
 
+    // Check the state of the game
+    match state.state {
+        ContractState::Init | ContractState::Open => {
+            return Err(ContractError::StillWaitingForPlayers);
         },
         // Has a player already won the game?
         ContractState::Done => {
             return Err(ContractError::GameIsAlreadyOver);
         },
+        ContractState::Full => {},
+    }
+
+    // validate players
+    if !state.is_player(&info.sender) {
+        return Err(ContractError::YouAreNotAPlayer);
+    }
+
+    // in commit-reveal mode, the secrets are only known once all players revealed them
+    if !state.players.iter().all(|player| player.has_revealed()) {
+        return Err(ContractError::StillWaitingForReveals);
     }
 
-    config(deps.storage).save(&state)?;
+    let contenders: Vec<&DiceRoller> = state.players.iter().collect();
+    let (dice_faces, winner) = pick_winner(&env, &state.dice, &contenders);
+    let dice_roll: u16 = dice_faces.iter().map(|face| *face as u16).sum();
+
+    state.dice_faces = dice_faces;
+    state.dice_roll = Some(dice_roll);
+    let messages = finish_game(deps.storage, &env, &config_state, game_id, &mut state, winner)?;
 
     Ok(Response::new()
-        .add_messages(messages)
+        .add_submessages(messages)
         .add_attribute("action", "roll dice")
+        .add_attribute("game_id", game_id.to_string())
         .add_attribute("result", dice_roll.to_string()))
 }
 
-pub fn try_leave(
+/// Picks the winner among `contenders`, who all have the same chance to win, and returns the
+/// faces the dice landed on. The dice are only rolled if there is more than one contender
+fn pick_winner(
+    env: &Env,
+    dice: &Dice,
+    contenders: &[&DiceRoller],
+) -> (Vec<u8>, Winner) {
+    let (dice_faces, seat) = if contenders.len() > 1 {
+        let secrets: Vec<Uint128> = contenders.iter().map(|player| *player.secret()).collect();
+        let random_seed = derive_seed(&secrets, &env.block.time, block_random(env));
+        let mut rng = ChaChaRng::from_seed(random_seed);
+
+        roll_for_seat(&mut rng, dice, contenders.len() as u8)
+    } else {
+        (vec![], 0)
+    };
+
+    let winner = contenders[seat];
+    (dice_faces, Winner::new(winner.name().to_string(), winner.addr().clone()))
+}
+
+/// Entropy the players can't predict, on chains that support it
+#[cfg(feature = "random")]
+fn block_random(env: &Env) -> Option<&Binary> {
+    env.block.random.as_ref()
+}
+
+#[cfg(not(feature = "random"))]
+fn block_random(_env: &Env) -> Option<&Binary> {
+    None
+}
+
+/// Rolls the dice until their total maps to a seat, so that no seat is favored.
+///
+/// With a single die, each seat gets the same number of consecutive faces and the faces left over
+/// are rolled again. With several dice, 2 players play for the totals below and above the average
+/// total, which are as likely, and the average itself is rolled again
+fn roll_for_seat(
+    rng: &mut ChaChaRng,
+    dice: &Dice,
+    seats: u8,
+) -> (Vec<u8>, usize) {
+    let faces_per_seat = (dice.sides / seats) as u16;
+
+    loop {
+        let dice_faces: Vec<u8> = (0..dice.count).map(|_| roll_die(rng, dice.sides)).collect();
+        let total: u16 = dice_faces.iter().map(|face| *face as u16).sum();
+
+        if dice.count == 1 {
+            if total <= faces_per_seat * seats as u16 {
+                return (dice_faces, ((total - 1) / faces_per_seat) as usize);
+            }
+            continue;
+        }
+
+        // comparing twice the total with the sum of the extremes avoids halving an odd number
+        let doubled = 2 * total;
+        let extremes = dice.min_total() + dice.max_total();
+        if doubled < extremes {
+            return (dice_faces, 0);
+        } else if doubled > extremes {
+            return (dice_faces, 1);
+        }
+    }
+}
+
+pub fn try_bet(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    prediction: Prediction,
+    dice: Option<Dice>,
+    secret: Uint128,
 ) -> Result<Response, ContractError> {
-    let mut state = config(deps.storage).load()?;
+    let config_state = config_read(deps.storage).load()?;
+    deposit_funds(&info, &config_state.stake)?;
 
-    let player_1 = if let Some(player_1) = &state.player_1 {
-        player_1
-    } else {
-        return Err(ContractError::PlayerOneNotFound);
-    };
+    place_bet(deps, env, &config_state, info.sender, prediction, dice, secret)
+}
 
-    // if player 2 isn't in yet, player 1 can leave and get their money back
-    if player_1.addr().as_ref() != info.sender {
-        return Err(ContractError::YouAreNotAPlayer);
+/// Places a bet of the stake against the house. A right prediction pays what the stake would
+/// fairly win given its odds, minus the house edge, and the house keeps the stake otherwise. The
+/// dice are only rolled once the bet is settled, in a later block
+fn place_bet(
+    deps: DepsMut,
+    env: Env,
+    config_state: &Config,
+    player: Addr,
+    prediction: Prediction,
+    dice: Option<Dice>,
+    secret: Uint128,
+) -> Result<Response, ContractError> {
+    if config_state.status != ContractStatus::Normal {
+        return Err(ContractError::JoinsAreStopped);
     }
 
-    // if we have both player 1 and player 2, game is in progress
-    if state.player_2.is_some() && state.state != ContractState::Done {
-        return Err(ContractError::GameIsInProgress);
+    // the house seed is only private when storage is encrypted
+    if config_state.commit_reveal.is_some() {
+        return Err(ContractError::BetsNeedEncryption);
     }
 
-    if state.state == ContractState::Done {
-        return Err(ContractError::GameIsAlreadyOver);
+    let dice = dice.unwrap_or_default();
+    check_dice(&dice)?;
+
+    let (winning, outcomes) = count_outcomes(&dice, &prediction);
+    if winning == 0 || winning == outcomes {
+        return Err(ContractError::InvalidPrediction);
+    }
+
+    let stake = config_state.stake.amount;
+    let house_share = (MAX_FEE_BPS - config_state.house_edge_bps) as u128;
+    let funds = bankroll_read(deps.storage).may_load()?.unwrap_or_default();
+    let max_win = max_win(config_state, funds);
+    let exposure_exceeded = || ContractError::ExposureLimitExceeded(Coin {
+        amount: max_win,
+        denom: config_state.stake.denom.clone(),
+    });
+
+    // no single bet can win more than a fraction of the bankroll, so the house can always pay
+    let payout = stake
+        .checked_multiply_ratio(outcomes * house_share, winning * MAX_FEE_BPS as u128)
+        .map_err(|_| exposure_exceeded())?;
+    if payout.saturating_sub(stake) > max_win {
+        return Err(exposure_exceeded());
     }
 
-    state.state = ContractState::Init;
+    let bet = Bet {
+        player,
+        prediction,
+        dice,
+        secret,
+        stake,
+        payout,
+        block_height: env.block.height,
+        outcome: None,
+    };
 
-    config(deps.storage).save(&state)?;
+    // the stake goes to the bankroll, and what the bet may take back is set aside until it's settled
+    let funds = funds.checked_add(stake).map_err(StdError::from)?
+        .checked_sub(bet.reserved()).map_err(StdError::from)?;
+    bankroll(deps.storage).save(&funds)?;
 
-    // Player 1 leaves the game before another player can join, and gets a refund on their deposit
-    let messages: Vec<CosmosMsg> = vec![CosmosMsg::Bank(BankMsg::Send {
-        to_address: player_1.addr().to_string(),
-        amount: vec![Coin::new(1_000_000, "uscrt")], // 1mn uscrt = 1 SCRT];
-    })];
+    let bet_id = bet_count_read(deps.storage).may_load()?.unwrap_or_default();
+    bets(deps.storage).save(&bet_id.to_be_bytes(), &bet)?;
+    bet_count(deps.storage).save(&(bet_id + 1))?;
 
     Ok(Response::new()
-        .add_messages(messages)
-        .add_attributes(vec![
-            ("action", "leave"),
-            ("receiver", player_1.name()),
-    ]))
+        .set_data(to_binary(&ExecuteAnswer::Bet { bet_id })?)
+        .add_attribute("action", "bet")
+        .add_attribute("bet_id", bet_id.to_string()))
 }
 
-///////////////////////////////////////////////////////////////////////
-//////////////////////////////// Query ////////////////////////////////
-///////////////////////////////////////////////////////////////////////
-
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(
-    deps: Deps,
+/// Rolls the dice of a bet with the seed of a block after the one it was placed in. Neither the
+/// response nor the `Bet` query tell the outcome before the block is over, and contracts, which
+/// could look at the outcome in a reply and revert a losing bet, can't settle bets
+pub fn try_settle_bet(
+    deps: DepsMut,
     env: Env,
-    msg: QueryMsg
-) -> StdResult<QueryResponse> {
-    match msg {
-        QueryMsg::WhoWon {} => to_binary(&query_who_won(deps, env)?),
+    info: MessageInfo,
+    bet_id: u64,
+) -> Result<Response, ContractError> {
+    if is_contract(deps.as_ref(), &info.sender) {
+        return Err(ContractError::ContractsCannotSettleBets);
     }
-}
 
-fn query_who_won(
-    deps: Deps,
-    env: Env,
-) -> StdResult<WinnerResponse> {
+    let mut bet = bets_read(deps.storage)
+        .may_load(&bet_id.to_be_bytes())?
+        .ok_or(ContractError::BetNotFound)?;
+    if bet.outcome.is_some() {
+        return Err(ContractError::BetAlreadySettled);
+    }
+    if env.block.height <= bet.block_height {
+        return Err(ContractError::BetNotSettleableYet);
+    }
 
-    let state = config_read(deps.storage).load()?;
+    let config_state = config_read(deps.storage).load()?;
+    let funds = bankroll_read(deps.storage).may_load()?.unwrap_or_default();
 
-    if state.state != ContractState::Done {
-        return Err(StdError::generic_err("No winner yet."));
-    }
+    // while everything is stopped, bets are refunded instead of played
+    if config_state.status == ContractStatus::StopAll {
+        let funds = funds.checked_add(bet.reserved() - bet.stake).map_err(StdError::from)?;
+        bankroll(deps.storage).save(&funds)?;
+        bets(deps.storage).remove(&bet_id.to_be_bytes());
 
-    // check that the query is happening after the block where the winner is decided
-    let winner_height = block_height_read(deps.storage).load()?;
-    let current_height = env.block.height;
+        return Ok(Response::new()
+            .add_message(payout_msg(&config_state, &bet.player, bet.stake)?)
+            .add_attribute("action", "settle bet")
+            .add_attribute("bet_id", bet_id.to_string()));
+    }
 
-    if current_height <= winner_height {
-        return Err(
-            StdError::generic_err(
-                "Querying who won is not allowed until after the winner has been finalized."
-        ));
+    let seed = derive_bet_seed(
+        &house_seed_read(deps.storage).load()?,
+        &bet.secret,
+        &env.block.time,
+        block_random(&env),
+    );
+    house_seed(deps.storage).save(&Sha256::digest(&seed).to_vec())?;
+
+    let mut rng = ChaChaRng::from_seed(seed);
+    let dice_faces: Vec<u8> = (0..bet.dice.count).map(|_| roll_die(&mut rng, bet.dice.sides)).collect();
+    let dice_roll: u16 = dice_faces.iter().map(|face| *face as u16).sum();
+    let won = bet.prediction.is_right(dice_roll);
+
+    // what the bet didn't win goes back to the bankroll
+    let paid = if won { bet.payout } else { Uint128::zero() };
+    let funds = funds.checked_add(bet.reserved() - paid).map_err(StdError::from)?;
+    bankroll(deps.storage).save(&funds)?;
+
+    // a small stake can round down to nothing, which can't be sent
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !paid.is_zero() {
+        messages.push(payout_msg(&config_state, &bet.player, paid)?);
     }
 
-    let dice_roll = if let Some(dice_roll) = state.dice_roll {
-        dice_roll
-    } else {
-        return Err(StdError::generic_err("Dice roll not found."));
-    };
+    bet.outcome = Some(BetOutcome { dice_faces, dice_roll, won, block_height: env.block.height });
+    bets(deps.storage).save(&bet_id.to_be_bytes(), &bet)?;
 
-    let winner = if let Some(winner) = &state.winner {
-        winner
-    } else {
-        return Err(StdError::generic_err("Winner not found."));
-    };
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "settle bet")
+        .add_attribute("bet_id", bet_id.to_string()))
+}
 
-    let resp = WinnerResponse {
-        name: winner.name().to_string(),
-        addr: winner.addr().clone(),
-        dice_roll,
-    };
-        
-    Ok(resp)
+/// Whether `addr` is a contract rather than an account
+fn is_contract(deps: Deps, addr: &Addr) -> bool {
+    let query = WasmQuery::ContractInfo { contract_addr: addr.to_string() };
+    deps.querier.query::<ContractInfoResponse>(&query.into()).is_ok()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, Coin, from_binary, QueryRequest, BankQuery::Balance, BalanceResponse};
+/// The most a bet can win on top of its stake with the bankroll at `funds`
+fn max_win(
+    config_state: &Config,
+    funds: Uint128,
+) -> Uint128 {
+    funds.multiply_ratio(config_state.max_exposure_bps, MAX_FEE_BPS)
+}
 
-    #[test]
-    fn proper_instantialization() {
-        let mut deps = mock_dependencies();
+/// Counts the outcomes of a roll of the `dice` for which the `prediction` is right, and all the
+/// outcomes. An outcome is the face of every die, e.g. with 2d6 a total of 7 is 6 outcomes of 36
+fn count_outcomes(
+    dice: &Dice,
+    prediction: &Prediction,
+) -> (u128, u128) {
+    // the number of ways the dice rolled so far can add up to each total
+    let mut ways: Vec<u128> = vec![1];
+    for _ in 0..dice.count {
+        let mut next_ways = vec![0u128; ways.len() + dice.sides as usize];
+        for (total, count) in ways.iter().enumerate() {
+            for face in 1..=dice.sides as usize {
+                next_ways[total + face] += count;
+            }
+        }
+        ways = next_ways;
+    }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
+    let winning = ways
+        .iter()
+        .enumerate()
+        .filter(|(total, _)| prediction.is_right(*total as u16))
+        .map(|(_, count)| count)
+        .sum();
 
-        // we can just call .unwrap() to assert this was a success
+    (winning, ways.iter().sum())
+}
+
+/// Ends a game won by `winner`, who takes the pot minus the house fee, and archives it
+fn finish_game(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config_state: &Config,
+    game_id: u64,
+    state: &mut State,
+    winner: Winner,
+) -> Result<Vec<SubMsg>, ContractError> {
+    // saving the block height so that the winner cannot be queried in the same block
+    block_height(storage).save(&game_key(game_id), &env.block.height)?;
+
+    // Winner takes all, minus the house fee
+    let pot = config_state.stake.amount
+        .checked_mul(Uint128::from(state.players.len() as u64))
+        .map_err(StdError::from)?;
+    let fee = house_fee(pot, config_state.fee_bps);
+    if !fee.is_zero() {
+        let accrued = treasury_read(storage).may_load()?.unwrap_or_default();
+        treasury(storage).save(&accrued.checked_add(fee).map_err(StdError::from)?)?;
+    }
+    let mut messages = vec![SubMsg::new(payout_msg(config_state, winner.addr(), pot - fee)?)];
+
+    // the callback contract can't revert the payout, nor keep the game from finishing
+    if let Some(callback) = &state.callback {
+        let msg = CallbackMsg::GameFinished {
+            game_id,
+            winner: winner.addr().clone(),
+            dice_roll: state.dice_roll,
+            msg: callback.msg.clone(),
+        };
+        let execute = WasmMsg::Execute {
+            contract_addr: callback.contract_addr.to_string(),
+            code_hash: callback.code_hash.clone(),
+            msg: padded(&msg)?,
+            funds: vec![],
+        };
+        messages.push(SubMsg::reply_on_error(execute, CALLBACK_REPLY_ID).with_gas_limit(CALLBACK_GAS_LIMIT));
+    }
+
+    state.winner = Some(winner);
+    state.state = ContractState::Done;
+    archive_game(storage, env, game_id, state, &config_state.stake)?;
+    untrack_deposits(storage, state, game_id)?;
+    games(storage).save(&game_key(game_id), state)?;
+
+    Ok(messages)
+}
+
+/// Lets a player leave a game before it is full and get their deposit back. The game is cleared
+/// when its last player leaves
+pub fn try_leave(
+    deps: DepsMut,
+    info: MessageInfo,
+    game_id: u64,
+) -> Result<Response, ContractError> {
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    if state.players.is_empty() {
+        return Err(ContractError::PlayerOneNotFound);
+    }
+
+    let seat = state.players
+        .iter()
+        .position(|player| player.addr() == &info.sender)
+        .ok_or(ContractError::YouAreNotAPlayer)?;
+
+    // once all the seats are taken, the game is in progress
+    match state.state {
+        ContractState::Full => return Err(ContractError::GameIsInProgress),
+        ContractState::Done => return Err(ContractError::GameIsAlreadyOver),
+        ContractState::Init | ContractState::Open => {},
+    }
+
+    // free the seat, so that the deposit can't be refunded twice
+    let player = state.players.remove(seat);
+    untrack_deposit(deps.storage, player.addr(), game_id)?;
+    if state.players.is_empty() {
+        reset_game(deps.storage, &state, game_id)?;
+    } else {
+        games(deps.storage).save(&game_key(game_id), &state)?;
+    }
+
+    let config_state = config_read(deps.storage).load()?;
+
+    // The player leaves the game before it is full, and gets a refund on their deposit
+    let messages: Vec<CosmosMsg> = vec![
+        payout_msg(&config_state, player.addr(), config_state.stake.amount)?,
+    ];
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            ("action", "leave"),
+            ("game_id", &game_id.to_string()),
+            ("receiver", player.name()),
+    ]))
+}
+
+/// Lets the players of a full game back out by mutual agreement. Each request is recorded, and
+/// once every player asked, they are all refunded and the game is cleared. Before the game is full
+/// players don't need anyone's agreement and this is the same as `Leave`
+pub fn try_cancel(
+    deps: DepsMut,
+    info: MessageInfo,
+    game_id: u64,
+) -> Result<Response, ContractError> {
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    match state.state {
+        ContractState::Init | ContractState::Open => return try_leave(deps, info, game_id),
+        ContractState::Done => return Err(ContractError::GameIsAlreadyOver),
+        ContractState::Full => {},
+    }
+
+    let player = state.players
+        .iter()
+        .find(|player| player.addr() == &info.sender)
+        .ok_or(ContractError::YouAreNotAPlayer)?
+        .clone();
+
+    if state.cancel_requested_by.contains(player.addr()) {
+        return Err(ContractError::CancellationAlreadyRequested);
+    }
+    state.cancel_requested_by.push(player.addr().clone());
+
+    // the other players are still to agree
+    if state.cancel_requested_by.len() < state.players.len() {
+        games(deps.storage).save(&game_key(game_id), &state)?;
+
+        return Ok(Response::new()
+            .add_attributes(vec![
+                ("action", "request cancel"),
+                ("game_id", &game_id.to_string()),
+                ("requested_by", player.name()),
+            ]));
+    }
+
+    // everyone agrees to cancel
+    let config_state = config_read(deps.storage).load()?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for other_player in &state.players {
+        messages.push(payout_msg(&config_state, other_player.addr(), config_state.stake.amount)?);
+    }
+
+    reset_game(deps.storage, &state, game_id)?;
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            ("action", "cancel"),
+            ("game_id", &game_id.to_string()),
+            ("agreed_by", player.name()),
+        ]))
+}
+
+/// Settles a game that is stuck past its deadline. The players are refunded if the game didn't
+/// fill up in time, or if nobody rolled the dice in time. In commit-reveal games the players who
+/// revealed play for the pot without the ones who didn't, and if nobody did all are refunded
+pub fn try_claim_timeout(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    game_id: u64,
+) -> Result<Response, ContractError> {
+    let mut state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    let config_state = config_read(deps.storage).load()?;
+    let mut messages: Vec<SubMsg> = vec![];
+
+    match state.state {
+        ContractState::Init => {
+            return Err(ContractError::NothingToClaim);
+        },
+        ContractState::Done => {
+            return Err(ContractError::GameIsAlreadyOver);
+        },
+        ContractState::Open | ContractState::Full => {},
+    }
+
+    if !state.is_player(&info.sender) {
+        return Err(ContractError::YouAreNotAPlayer);
+    }
+
+    let winner = if state.state == ContractState::Open {
+        // the game didn't fill up in time
+        check_expired(&state.deadline, &env)?;
+        None
+    } else if state.players.iter().all(|player| player.has_revealed()) {
+        // all secrets are known, but nobody rolled the dice in time
+        check_expired(&state.deadline, &env)?;
+        None
+    } else {
+        check_expired(&state.reveal_deadline, &env)?;
+
+        // the players who didn't reveal forfeit their deposit, unless everything is stopped and
+        // only refunds are allowed
+        let contenders: Vec<&DiceRoller> = state.players
+            .iter()
+            .filter(|player| player.has_revealed())
+            .collect();
+        if contenders.is_empty() || config_state.status == ContractStatus::StopAll {
+            None
+        } else {
+            Some(pick_winner(&env, &state.dice, &contenders))
+        }
+    };
+
+    match winner {
+        Some((dice_faces, winner)) => {
+            if !dice_faces.is_empty() {
+                state.dice_roll = Some(dice_faces.iter().map(|face| *face as u16).sum());
+            }
+            state.dice_faces = dice_faces;
+            messages = finish_game(deps.storage, &env, &config_state, game_id, &mut state, winner)?;
+        },
+        // otherwise all deposits are refunded and the game can be played again
+        None => {
+            for player in &state.players {
+                messages.push(SubMsg::new(payout_msg(&config_state, player.addr(), config_state.stake.amount)?));
+            }
+            reset_game(deps.storage, &state, game_id)?;
+        },
+    }
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attribute("action", "claim timeout")
+        .add_attribute("game_id", game_id.to_string()))
+}
+
+/// The part of `pot` kept by the house. It is rounded down, so any remainder goes to the winner
+fn house_fee(pot: Uint128, fee_bps: u16) -> Uint128 {
+    pot.multiply_ratio(fee_bps, MAX_FEE_BPS)
+}
+
+/// Remembers that `player` has a deposit in the game, so that it shows up in their balance
+fn track_deposit(
+    storage: &mut dyn Storage,
+    player: &Addr,
+    game_id: u64,
+) -> StdResult<()> {
+    let mut game_ids = player_games_read(storage).may_load(player.as_bytes())?.unwrap_or_default();
+    game_ids.push(game_id);
+    player_games(storage).save(player.as_bytes(), &game_ids)?;
+
+    add_open_game(storage, game_id)
+}
+
+/// Forgets the deposit of a player who left or was paid
+fn untrack_deposit(
+    storage: &mut dyn Storage,
+    player: &Addr,
+    game_id: u64,
+) -> StdResult<()> {
+    let key = player.as_bytes();
+    let mut game_ids = player_games_read(storage).may_load(key)?.unwrap_or_default();
+    if let Some(position) = game_ids.iter().position(|id| *id == game_id) {
+        game_ids.remove(position);
+    }
+
+    if game_ids.is_empty() {
+        player_games(storage).remove(key);
+    } else {
+        player_games(storage).save(key, &game_ids)?;
+    }
+
+    Ok(())
+}
+
+/// Forgets the deposits of the players of a game that ended or was reset
+fn untrack_deposits(
+    storage: &mut dyn Storage,
+    state: &State,
+    game_id: u64,
+) -> StdResult<()> {
+    for player in &state.players {
+        untrack_deposit(storage, player.addr(), game_id)?;
+    }
+
+    remove_open_game(storage, game_id)
+}
+
+/// Clears a game whose deposits were refunded, so that it can be played again
+fn reset_game(
+    storage: &mut dyn Storage,
+    state: &State,
+    game_id: u64,
+) -> StdResult<()> {
+    untrack_deposits(storage, state, game_id)?;
+    games(storage).remove(&game_key(game_id));
+
+    Ok(())
+}
+
+/// Adds a game that just ended to the history
+fn archive_game(
+    storage: &mut dyn Storage,
+    env: &Env,
+    game_id: u64,
+    state: &State,
+    stake: &Coin,
+) -> StdResult<u64> {
+    let winner = state.winner.as_ref()
+        .ok_or_else(|| StdError::generic_err("Winner not found."))?;
+
+    let players = state.players
+        .iter()
+        .map(|player| PlayerRecord { name: player.name().to_string(), addr: player.addr().clone() })
+        .collect();
+
+    append_history(storage, GameRecord {
+        index: 0,
+        game_id,
+        players,
+        stake: stake.clone(),
+        dice: state.dice.clone(),
+        dice_faces: state.dice_faces.clone(),
+        dice_roll: state.dice_roll,
+        winner: PlayerRecord { name: winner.name().to_string(), addr: winner.addr().clone() },
+        block_height: env.block.height,
+        timestamp: env.block.time,
+    })
+}
+
+/// Checks that the game has a deadline and that it has passed
+fn check_expired(
+    deadline: &Option<Expiration>,
+    env: &Env,
+) -> Result<(), ContractError> {
+    match deadline {
+        None => Err(ContractError::NothingToClaim),
+        Some(deadline) if !deadline.is_expired(&env.block) => Err(ContractError::DeadlineNotReached),
+        Some(_) => Ok(()),
+    }
+}
+
+pub fn try_create_viewing_key(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    entropy: String,
+) -> Result<Response, ContractError> {
+    let key = ViewingKey::create(deps.storage, &info, &env, info.sender.as_str(), entropy.as_bytes());
+
+    Ok(Response::new()
+        .set_data(to_binary(&ExecuteAnswer::CreateViewingKey { key })?))
+}
+
+pub fn try_set_viewing_key(
+    deps: DepsMut,
+    info: MessageInfo,
+    key: String,
+) -> Result<Response, ContractError> {
+    ViewingKey::set(deps.storage, info.sender.as_str(), &key);
+
+    Ok(Response::new()
+        .add_attribute("action", "set viewing key"))
+}
+
+pub fn try_revoke_permit(
+    deps: DepsMut,
+    info: MessageInfo,
+    permit_name: String,
+) -> Result<Response, ContractError> {
+    RevokedPermits::revoke_permit(
+        deps.storage,
+        PREFIX_REVOKED_PERMITS,
+        info.sender.as_str(),
+        &permit_name,
+    );
+
+    Ok(Response::new()
+        .add_attribute("action", "revoke permit"))
+}
+
+/// Loads the config, checking that the sender is the admin
+fn admin_config(
+    storage: &dyn Storage,
+    info: &MessageInfo,
+) -> Result<Config, ContractError> {
+    let config_state = config_read(storage).load()?;
+    if config_state.admin != info.sender {
+        return Err(ContractError::NotAdmin);
+    }
+
+    Ok(config_state)
+}
+
+pub fn try_change_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let mut config_state = admin_config(deps.storage, &info)?;
+    config_state.admin = deps.api.addr_validate(&address)?;
+    config(deps.storage).save(&config_state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "change admin")
+        .add_attribute("admin", address))
+}
+
+pub fn try_set_status(
+    deps: DepsMut,
+    info: MessageInfo,
+    status: ContractStatus,
+) -> Result<Response, ContractError> {
+    let mut config_state = admin_config(deps.storage, &info)?;
+    config_state.status = status;
+    config(deps.storage).save(&config_state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set status"))
+}
+
+/// Refunds every player of the unfinished games and clears them. Only possible once the contract
+/// is stopped, so that no game can start or finish in the meantime
+pub fn try_emergency_refund(
+    deps: DepsMut,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config_state = admin_config(deps.storage, &info)?;
+    if config_state.status != ContractStatus::StopAll {
+        return Err(ContractError::ContractIsNotStopped);
+    }
+
+    let open_game_count = open_game_count_read(deps.storage).may_load()?.unwrap_or_default();
+    let refunded = limit.map_or(open_game_count, |limit| open_game_count.min(limit as u64));
+
+    // the last open game is refunded first, so that removing it moves no other game
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for position in (open_game_count - refunded..open_game_count).rev() {
+        let game_id = open_games_read(deps.storage).load(&position.to_be_bytes())?;
+        let state = games_read(deps.storage)
+            .may_load(&game_key(game_id))?
+            .unwrap_or_default();
+
+        for player in &state.players {
+            messages.push(payout_msg(&config_state, player.addr(), config_state.stake.amount)?);
+        }
+        reset_game(deps.storage, &state, game_id)?;
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "emergency refund")
+        .add_attribute("refunded_games", refunded.to_string())
+        .add_attribute("remaining_games", (open_game_count - refunded).to_string()))
+}
+
+pub fn try_withdraw_fees(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config_state = admin_config(deps.storage, &info)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    // a zero amount can't be sent
+    if amount.is_zero() {
+        return Err(ContractError::ZeroWithdrawal);
+    }
+
+    let accrued = treasury_read(deps.storage).may_load()?.unwrap_or_default();
+    let remaining = accrued.checked_sub(amount).map_err(|_| {
+        ContractError::InsufficientFees(Coin { amount: accrued, denom: config_state.stake.denom.clone() })
+    })?;
+    treasury(deps.storage).save(&remaining)?;
+
+    Ok(Response::new()
+        .add_message(payout_msg(&config_state, &recipient, amount)?)
+        .add_attribute("action", "withdraw fees")
+        .add_attribute("amount", amount.to_string()))
+}
+
+pub fn try_deposit_bankroll(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config_state = config_read(deps.storage).load()?;
+
+    // tokens are deposited through the SNIP-20 receiver interface
+    let amount = match (&config_state.token, info.funds.as_slice()) {
+        (None, [coin]) if coin.denom == config_state.stake.denom => coin.amount,
+        _ => return Err(ContractError::InvalidBankrollDeposit(config_state.stake.denom)),
+    };
+
+    deposit_bankroll(deps.storage, &config_state, &info.sender, amount)
+}
+
+/// Adds `amount` sent by `sender`, who must be the admin, to the bankroll
+fn deposit_bankroll(
+    storage: &mut dyn Storage,
+    config_state: &Config,
+    sender: &Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if config_state.admin != *sender {
+        return Err(ContractError::NotAdmin);
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidBankrollDeposit(config_state.stake.denom.clone()));
+    }
+
+    let funds = bankroll_read(storage).may_load()?.unwrap_or_default();
+    bankroll(storage).save(&funds.checked_add(amount).map_err(StdError::from)?)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "deposit bankroll")
+        .add_attribute("amount", amount.to_string()))
+}
+
+pub fn try_withdraw_bankroll(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config_state = admin_config(deps.storage, &info)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    // a zero amount can't be sent
+    if amount.is_zero() {
+        return Err(ContractError::ZeroWithdrawal);
+    }
+
+    let funds = bankroll_read(deps.storage).may_load()?.unwrap_or_default();
+    let remaining = funds.checked_sub(amount).map_err(|_| {
+        ContractError::InsufficientBankroll(Coin { amount: funds, denom: config_state.stake.denom.clone() })
+    })?;
+    bankroll(deps.storage).save(&remaining)?;
+
+    Ok(Response::new()
+        .add_message(payout_msg(&config_state, &recipient, amount)?)
+        .add_attribute("action", "withdraw bankroll")
+        .add_attribute("amount", amount.to_string()))
+}
+
+pub fn try_set_max_exposure(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_exposure_bps: u16,
+) -> Result<Response, ContractError> {
+    let mut config_state = admin_config(deps.storage, &info)?;
+    if max_exposure_bps > MAX_FEE_BPS {
+        return Err(StdError::generic_err("The max exposure can't be more than 10000 basis points.").into());
+    }
+
+    config_state.max_exposure_bps = max_exposure_bps;
+    config(deps.storage).save(&config_state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set max exposure")
+        .add_attribute("max_exposure_bps", max_exposure_bps.to_string()))
+}
+
+///////////////////////////////////////////////////////////////////////
+//////////////////////////////// Reply ////////////////////////////////
+///////////////////////////////////////////////////////////////////////
+
+#[cfg_attr(not(feature = "library"), cosmwasm_std::entry_point)]
+pub fn reply(
+    _deps: DepsMut,
+    _env: Env,
+    msg: Reply,
+) -> StdResult<Response> {
+    match msg.id {
+        // only failed callbacks are replied to. The game stays finished and its winner paid
+        CALLBACK_REPLY_ID => Ok(Response::new()
+            .add_attribute("action", "callback")
+            .add_attribute("result", "failed")),
+        id => Err(StdError::generic_err(format!("Unknown reply id {}.", id))),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////
+//////////////////////////////// Query ////////////////////////////////
+///////////////////////////////////////////////////////////////////////
+
+#[cfg_attr(not(feature = "library"), cosmwasm_std::entry_point)]
+pub fn query(
+    deps: Deps,
+    env: Env,
+    msg: QueryMsg
+) -> StdResult<QueryResponse> {
+    match msg {
+        QueryMsg::WhoWon { game_id } => to_binary(&query_who_won(deps, env, game_id)?),
+        QueryMsg::GameStatus { game_id } => to_binary(&query_game_status(deps, env, game_id)?),
+        QueryMsg::GameHistory { start_after, limit } => {
+            to_binary(&query_game_history(deps, env, start_after, limit)?)
+        },
+        QueryMsg::MyGames { address, key, start_after, limit } => {
+            let addr = check_viewing_key(deps, &address, &key)?;
+            to_binary(&query_player_history(deps, env, addr, start_after, limit)?)
+        },
+        QueryMsg::MyBalance { address, key } => {
+            let addr = check_viewing_key(deps, &address, &key)?;
+            to_binary(&query_balance(deps, addr)?)
+        },
+        QueryMsg::ContractStatus {} => to_binary(&query_contract_status(deps)?),
+        QueryMsg::Fees {} => to_binary(&query_fees(deps)?),
+        QueryMsg::Bankroll {} => to_binary(&query_bankroll(deps)?),
+        QueryMsg::Bet { bet_id } => to_binary(&query_bet(deps, env, bet_id)?),
+        QueryMsg::WithPermit { permit, query } => permit_query(deps, env, permit, query),
+    }
+}
+
+fn query_contract_status(deps: Deps) -> StdResult<ContractStatusResponse> {
+    let config_state = config_read(deps.storage).load()?;
+
+    Ok(ContractStatusResponse {
+        admin: config_state.admin,
+        status: config_state.status,
+    })
+}
+
+fn query_fees(deps: Deps) -> StdResult<FeesResponse> {
+    let config_state = config_read(deps.storage).load()?;
+
+    Ok(FeesResponse {
+        fee_bps: config_state.fee_bps,
+        accrued: Coin {
+            amount: treasury_read(deps.storage).may_load()?.unwrap_or_default(),
+            denom: config_state.stake.denom,
+        },
+    })
+}
+
+fn query_bankroll(deps: Deps) -> StdResult<BankrollResponse> {
+    let config_state = config_read(deps.storage).load()?;
+    let funds = bankroll_read(deps.storage).may_load()?.unwrap_or_default();
+
+    Ok(BankrollResponse {
+        balance: Coin { amount: funds, denom: config_state.stake.denom.clone() },
+        max_exposure_bps: config_state.max_exposure_bps,
+        max_win: Coin { amount: max_win(&config_state, funds), denom: config_state.stake.denom },
+    })
+}
+
+fn query_bet(
+    deps: Deps,
+    env: Env,
+    bet_id: u64,
+) -> StdResult<BetResponse> {
+    let denom = config_read(deps.storage).load()?.stake.denom;
+    let bet = bets_read(deps.storage)
+        .may_load(&bet_id.to_be_bytes())?
+        .ok_or_else(|| StdError::generic_err("Bet not found."))?;
+
+    Ok(BetResponse {
+        prediction: bet.prediction,
+        dice: bet.dice,
+        stake: Coin { amount: bet.stake, denom: denom.clone() },
+        payout: Coin { amount: bet.payout, denom },
+        outcome: bet.outcome.filter(|outcome| outcome.block_height < env.block.height),
+    })
+}
+
+/// Answers a private query for the signer of `permit`, once its signature, its scope and the
+/// permission needed by the query are checked
+fn permit_query(
+    deps: Deps,
+    env: Env,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> StdResult<QueryResponse> {
+    let account = validate(
+        deps,
+        PREFIX_REVOKED_PERMITS,
+        &permit,
+        env.contract.address.to_string(),
+        None,
+    )?;
+    let addr = deps.api.addr_validate(&account)?;
+
+    match query {
+        QueryWithPermit::MyGames { start_after, limit } => {
+            check_permission(&permit, TokenPermissions::History)?;
+            to_binary(&query_player_history(deps, env, addr, start_after, limit)?)
+        },
+        QueryWithPermit::MyBalance {} => {
+            check_permission(&permit, TokenPermissions::Balance)?;
+            to_binary(&query_balance(deps, addr)?)
+        },
+    }
+}
+
+fn query_who_won(
+    deps: Deps,
+    env: Env,
+    game_id: u64,
+) -> StdResult<WinnerResponse> {
+
+    let state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    if state.state != ContractState::Done {
+        return Err(StdError::generic_err("No winner yet."));
+    }
+
+    // check that the query is happening after the block where the winner is decided
+    if !winner_finalized(deps, &env, game_id)? {
+        return Err(
+            StdError::generic_err(
+                "Querying who won is not allowed until after the winner has been finalized."
+        ));
+    }
+
+    let winner = if let Some(winner) = &state.winner {
+        winner
+    } else {
+        return Err(StdError::generic_err("Winner not found."));
+    };
+
+    let resp = WinnerResponse {
+        name: winner.name().to_string(),
+        dice_faces: state.dice_faces,
+        dice_roll: state.dice_roll,
+    };
+        
+    Ok(resp)
+}
+
+/// Whether the block in which the game ended is over, so that its winner can be revealed
+fn winner_finalized(
+    deps: Deps,
+    env: &Env,
+    game_id: u64,
+) -> StdResult<bool> {
+    let winner_height = block_height_read(deps.storage).may_load(&game_key(game_id))?;
+
+    Ok(matches!(winner_height, Some(winner_height) if env.block.height > winner_height))
+}
+
+fn query_game_status(
+    deps: Deps,
+    env: Env,
+    game_id: u64,
+) -> StdResult<GameStatusResponse> {
+
+    let state = games_read(deps.storage)
+        .may_load(&game_key(game_id))?
+        .unwrap_or_default();
+
+    let players = state.players
+        .iter()
+        .map(|player| PlayerResponse {
+            name: player.name().to_string(),
+            addr: player.addr().clone(),
+            revealed: player.has_revealed(),
+        })
+        .collect();
+
+    let winner_queryable = state.state == ContractState::Done
+        && winner_finalized(deps, &env, game_id)?;
+
+    Ok(GameStatusResponse {
+        state: state.state,
+        seats: state.seats,
+        dice: state.dice,
+        players,
+        stake: config_read(deps.storage).load()?.stake,
+        deadline: state.deadline,
+        reveal_deadline: state.reveal_deadline,
+        cancel_requested_by: state.cancel_requested_by,
+        winner_queryable,
+    })
+}
+
+fn query_game_history(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<GameHistoryResponse> {
+    let total = history_count_read(deps.storage).may_load()?.unwrap_or_default();
+    let start = start_after.map_or(0, |index| index.saturating_add(1));
+
+    let games = load_records(deps, &env, (start..total).map(Ok), limit)?
+        .into_iter()
+        .map(PublicGameRecord::from)
+        .collect();
+
+    Ok(GameHistoryResponse { games, total })
+}
+
+/// The history of a single player, only served to the player themselves
+fn query_player_history(
+    deps: Deps,
+    env: Env,
+    addr: Addr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<MyGamesResponse> {
+    let total = player_history_count_read(deps.storage)
+        .may_load(addr.as_bytes())?
+        .unwrap_or_default();
+    let indexes = player_history_read(deps.storage, &addr);
+
+    // the player's list is sorted by record index, so look for the first one after `start_after`
+    let mut start = 0;
+    if let Some(start_after) = start_after {
+        let mut end = total;
+        while start < end {
+            let middle = start + (end - start) / 2;
+            if indexes.load(&middle.to_be_bytes())? <= start_after {
+                start = middle + 1;
+            } else {
+                end = middle;
+            }
+        }
+    }
+
+    let games = load_records(
+        deps,
+        &env,
+        (start..total).map(|position| indexes.load(&position.to_be_bytes())),
+        limit,
+    )?;
+
+    Ok(MyGamesResponse { games, total })
+}
+
+fn query_balance(
+    deps: Deps,
+    addr: Addr,
+) -> StdResult<MyBalanceResponse> {
+    let stake = config_read(deps.storage).load()?.stake;
+    let game_ids = player_games_read(deps.storage).may_load(addr.as_bytes())?.unwrap_or_default();
+
+    Ok(MyBalanceResponse {
+        pending: Coin {
+            amount: stake.amount.checked_mul(Uint128::from(game_ids.len() as u64))?,
+            denom: stake.denom,
+        },
+        game_ids,
+    })
+}
+
+/// Returns the address if `key` is its viewing key. The keys are compared in constant time
+fn check_viewing_key(
+    deps: Deps,
+    address: &str,
+    key: &str,
+) -> StdResult<Addr> {
+    let addr = deps.api.addr_validate(address)?;
+
+    ViewingKey::check(deps.storage, addr.as_str(), key).map_err(|_| {
+        StdError::generic_err("Wrong viewing key for this address or viewing key not set.")
+    })?;
+
+    Ok(addr)
+}
+
+fn check_permission(permit: &Permit, permission: TokenPermissions) -> StdResult<()> {
+    if !permit.check_permission(&permission) {
+        return Err(StdError::generic_err(format!(
+            "The permit does not grant the {:?} permission, got {:?}",
+            permission,
+            permit.params.permissions,
+        )));
+    }
+
+    Ok(())
+}
+
+/// Loads a page of history records. Like `WhoWon`, games that ended in the current block are left
+/// out until the block is final
+fn load_records(
+    deps: Deps,
+    env: &Env,
+    indexes: impl Iterator<Item = StdResult<u64>>,
+    limit: Option<u32>,
+) -> StdResult<Vec<GameRecord>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let history = history_read(deps.storage);
+
+    let mut records = vec![];
+    for index in indexes.take(limit) {
+        let record = history.load(&index?.to_be_bytes())?;
+        if record.block_height >= env.block.height {
+            break;
+        }
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::OwnedDeps;
+    use cosmwasm_storage::to_length_prefixed;
+    use crate::state::{CommitRevealConfig, TokenContract};
+    use cosmwasm_std::{
+        attr, coins, Coin, from_binary, from_slice, QueryRequest, BankQuery::Balance, BalanceResponse, ReplyOn,
+        SubMsgResult, SystemResult, ContractResult, SystemError, BlockInfo,
+    };
+
+    const GAME_ID: u64 = 1;
+
+    fn instantiate_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            stake: Coin::new(1_000_000, "uscrt"),
+            token: None,
+            commit_reveal: None,
+            join_timeout: None,
+            roll_timeout: None,
+            prng_seed: Some(Binary::from(b"entropy")),
+            admin: None,
+            fee_bps: None,
+            house_edge_bps: None,
+            max_exposure_bps: None,
+        }
+    }
+
+    fn join_msg(game_id: u64, name: &str, secret: u128) -> ExecuteMsg {
+        ExecuteMsg::Join {
+            game_id,
+            name: name.to_string(),
+            seats: None,
+            dice: None,
+            secret: Some(Uint128::new(secret)),
+            commitment: None,
+            callback: None,
+        }
+    }
+
+    fn join_seats_msg(game_id: u64, name: &str, secret: u128, seats: u8) -> ExecuteMsg {
+        ExecuteMsg::Join {
+            game_id,
+            name: name.to_string(),
+            seats: Some(seats),
+            dice: None,
+            secret: Some(Uint128::new(secret)),
+            commitment: None,
+            callback: None,
+        }
+    }
+
+    #[test]
+    fn proper_instantialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+
+        // we can just call .unwrap() to assert this was a success
         let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(0, res.messages.len());
     }
 
     #[test]
-    fn still_waiting_for_players() {
+    fn still_waiting_for_players() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        // Player 1 tries to roll the dice -- should produce an error
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForPlayers);
+    }
+
+    #[test]
+    fn player_1_leaves_and_gets_refund() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        // Player 1 leaves the games and gets their 1 SCRT back
+        let _res = execute(deps.as_mut(), env, info, ExecuteMsg::Leave { game_id: GAME_ID }).unwrap();
+    }
+
+    #[test]
+    fn leave_when_game_in_progress() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info_player_1 = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info_player_1.clone(), msg).unwrap();
+
+        // Player 2 joins the game
+        let msg = join_msg(GAME_ID, "bob", 5678);
+        let info_player_2 = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info_player_2, msg).unwrap();
+
+        // Player 1 tries to leave the game, but it's in progress, awaiting a dice roll
+        let err = execute(deps.as_mut(), env, info_player_1, ExecuteMsg::Leave { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::GameIsInProgress);
+    }
+
+    #[test]
+    fn not_a_player() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        // Player 2 joins the game
+        let msg = join_msg(GAME_ID, "bob", 5678);
+        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Non-player tries to interact with the game
+        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Leave { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+
+        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+    }
+
+    #[test]
+    fn game_is_full() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 2 joins the game
+        let msg = join_msg(GAME_ID, "bob", 5678);
+        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // A 3rd player tries to join
+        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
+        let msg = join_msg(GAME_ID, "ted", 9810);
+        let err = execute(deps.as_mut(), env, info, msg).unwrap_err();
+        assert_eq!(err, ContractError::GameIsFull);
+    }
+
+    #[test]
+    fn no_winner_yet() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 2 joins the game
+        let msg = join_msg(GAME_ID, "bob", 5678);
+        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // there should be no winner yet since we didn't do a dice roll!
+        let err = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, StdError::generic_err("No winner yet."));
+    }
+
+    #[test]
+    fn query_not_allowed_in_same_block() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg_player_1 = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg_player_1).unwrap();
+
+        // Player 2 joins the game
+        let msg_player_2 = join_msg(GAME_ID, "bob", 5678);
+        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg_player_2).unwrap();
+
+        // Player 2 rolls the dice
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+
+        // should result in an error because execute and query on winner cannot be done in the same block height
+        let err = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, StdError::generic_err(
+            "Querying who won is not allowed until after the winner has been finalized."
+        ));
+    }
+
+    #[test]
+    fn roll_the_dice() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let uscrt_denom = "uscrt".to_string();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let msg_player_1 = join_msg(GAME_ID, "alice", 1234);
+        let info = mock_info("alice", &coins(1_000_000, uscrt_denom.clone()));
+        let _res = execute(deps.as_mut(), env.clone(), info, msg_player_1).unwrap();
+
+        let msg_player_2 = join_msg(GAME_ID, "bob", 5678);
+        let info = mock_info("bob", &coins(1_000_000, uscrt_denom.clone()));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg_player_2).unwrap();
+
+        // player 2 rolls the dice
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+
+        // advance block height by 1 to be able to query for winner
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap();
+        let value: WinnerResponse = from_binary(&res).unwrap();
+        assert!(!value.name.is_empty());
+
+        let msg = QueryRequest::Bank(
+            Balance {
+                address: value.name.clone(),
+                denom: uscrt_denom.clone(),
+            });
+        let _value: BalanceResponse = from_binary(&deps.querier.handle_query(&msg).unwrap().unwrap()).unwrap();
+        let _expected_amount = Coin { amount: Uint128::new(2_000_000), denom: uscrt_denom };
+
+        // TODO: verify winner's balance has increased by 2 SCRT
+        // assert_eq!(value.amount, Coin { amount: Uint128::new(2_000_000), denom: uscrt_denom });
+        // TODO: winner is always "alice" with a dice roll of 1 -- need to troubleshoot that
+    }
+
+    #[test]
+    fn game_is_already_over() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Player 1 joins the game
+        let msg = join_msg(GAME_ID, "alice", 1234);
+        let player_1_info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), player_1_info.clone(), msg).unwrap();
+
+        // Player 2 joins the game
+        let msg = join_msg(GAME_ID, "bob", 5678);
+        let player_2_info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), player_2_info.clone(), msg).unwrap();
+
+        // player 1 rolls the dice
+        let msg = ExecuteMsg::RollDice { game_id: GAME_ID };
+        let _res = execute(deps.as_mut(), env.clone(), player_1_info, msg.clone()).unwrap();
+
+        // player 2 tries to roll the dice when game is over
+        let err = execute(deps.as_mut(), env, player_2_info, msg).unwrap_err();
+        assert_eq!(err, ContractError::GameIsAlreadyOver);
+    }
+
+    #[test]
+    fn concurrent_games_are_independent() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1000, "earth"));
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // alice and bob play game 1
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(1, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob, join_msg(1, "bob", 5678)).unwrap();
+
+        // carol opens game 2 while game 1 is full
+        let carol = mock_info("carol", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), carol.clone(), join_msg(2, "carol", 42)).unwrap();
+
+        // game 1 finishes without affecting game 2
+        let _res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::RollDice { game_id: 1 }).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), carol.clone(), ExecuteMsg::RollDice { game_id: 2 }).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForPlayers);
+
+        // dave joins game 2 and it can be played to the end as well
+        let dave = mock_info("dave", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), dave, join_msg(2, "dave", 24)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), carol, ExecuteMsg::RollDice { game_id: 2 }).unwrap();
+
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: 1 }).unwrap();
+        let winner_1: WinnerResponse = from_binary(&res).unwrap();
+        assert!(winner_1.name == "alice" || winner_1.name == "bob");
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: 2 }).unwrap();
+        let winner_2: WinnerResponse = from_binary(&res).unwrap();
+        assert!(winner_2.name == "carol" || winner_2.name == "dave");
+
+        // a game that was never played has no winner
+        let err = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: 3 }).unwrap_err();
+        assert_eq!(err, StdError::generic_err("No winner yet."));
+    }
+
+    #[test]
+    fn zero_stake_is_rejected() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg { stake: Coin::new(0, "uscrt"), ..instantiate_msg() };
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, StdError::generic_err("The stake must be greater than zero."));
+    }
+
+    #[test]
+    fn stake_is_configurable() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let stake = Coin::new(250, "utest");
+
+        let msg = InstantiateMsg { stake: stake.clone(), ..instantiate_msg() };
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // the default 1 SCRT deposit is not accepted at this table
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), info, join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::MustDepositStakeToPlay(stake.clone()));
+
+        // right denom, wrong amount
+        let info = mock_info("alice", &coins(500, "utest"));
+        let err = execute(deps.as_mut(), env.clone(), info, join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::MustDepositStakeToPlay(stake.clone()));
+
+        // player 1 joins and leaves: the refund is the stake
+        let alice = mock_info("alice", &coins(250, "utest"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), ExecuteMsg::Leave { game_id: GAME_ID }).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![stake],
+        }));
+
+        // both players join and roll: the winner takes twice the stake
+        let bob = mock_info("bob", &coins(250, "utest"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob, join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let res = execute(deps.as_mut(), env, alice, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => {
+                assert_eq!(amount, &vec![Coin::new(500, "utest")]);
+            },
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn snip20_wagers() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let token = TokenContract {
+            address: Addr::unchecked("sscrt"),
+            code_hash: "sscrt_hash".to_string(),
+        };
+
+        // the stake denom has to match the token contract
+        let msg = InstantiateMsg { token: Some(token.clone()), ..instantiate_msg() };
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert_eq!(err, StdError::generic_err("The stake denom must be the token contract address."));
+
+        // the contract registers itself with the token on instantiation
+        let msg = InstantiateMsg {
+            stake: Coin::new(1_000_000, "sscrt"),
+            token: Some(token.clone()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &[]);
+        let res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        let receive = |from: &str, secret: u128, amount: u128| ExecuteMsg::Receive {
+            sender: Addr::unchecked(from),
+            from: Addr::unchecked(from),
+            amount: Uint128::new(amount),
+            msg: Some(to_binary(&ReceiveMsg::Join {
+                game_id: GAME_ID,
+                name: from.to_string(),
+                seats: None,
+                dice: None,
+                secret: Some(Uint128::new(secret)),
+                commitment: None,
+                callback: None,
+            }).unwrap()),
+        };
+
+        // native deposits are not accepted at a token table
+        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), info, join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::MustDepositStakeToPlay(Coin::new(1_000_000, "sscrt")));
+
+        // only the whitelisted token can deposit
+        let info = mock_info("other_token", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, receive("alice", 1234, 1_000_000)).unwrap_err();
+        assert_eq!(err, ContractError::UnsupportedToken);
+
+        // the deposit must match the stake
+        let info = mock_info("sscrt", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, receive("alice", 1234, 1)).unwrap_err();
+        assert_eq!(err, ContractError::MustDepositStakeToPlay(Coin::new(1_000_000, "sscrt")));
+
+        // both players join by sending tokens
+        let info = mock_info("sscrt", &[]);
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), receive("alice", 1234, 1_000_000)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), info, receive("bob", 5678, 1_000_000)).unwrap();
+
+        // the winner is paid with a token transfer
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        let winner = if res.attributes[2].value.parse::<u8>().unwrap() <= 3 { "alice" } else { "bob" };
+        let expected = transfer_msg(
+            winner.to_string(),
+            Uint128::new(2_000_000),
+            None,
+            None,
+            BLOCK_SIZE,
+            token.code_hash,
+            token.address.to_string(),
+        ).unwrap();
+        assert_eq!(res.messages[0].msg, expected);
+    }
+
+    fn commit_reveal_setup() -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, Env) {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+
+        let msg = InstantiateMsg {
+            commit_reveal: Some(CommitRevealConfig { reveal_timeout: 10 }),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        (deps, env)
+    }
+
+    fn commit_msg(name: &str, secret: u128, salt: &[u8]) -> ExecuteMsg {
+        let commitment = secret_commitment(&Uint128::new(secret), &Binary::from(salt));
+        ExecuteMsg::Join {
+            game_id: GAME_ID,
+            name: name.to_string(),
+            seats: None,
+            dice: None,
+            secret: None,
+            commitment: Some(Binary::from(commitment.to_vec())),
+            callback: None,
+        }
+    }
+
+    fn reveal_msg(secret: u128, salt: &[u8]) -> ExecuteMsg {
+        ExecuteMsg::Reveal { game_id: GAME_ID, secret: Uint128::new(secret), salt: Binary::from(salt) }
+    }
+
+    #[test]
+    fn commit_reveal_game() {
+        let (mut deps, env) = commit_reveal_setup();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+
+        // sending the secret itself is refused
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::MissingCommitment);
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), commit_msg("alice", 1234, b"salt_a")).unwrap();
+
+        // nobody can reveal before the other player committed
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_a")).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForPlayers);
+
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), commit_msg("bob", 5678, b"salt_b")).unwrap();
+
+        // the dice can't be rolled until both players revealed
+        let roll = ExecuteMsg::RollDice { game_id: GAME_ID };
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), roll.clone()).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForReveals);
+
+        // a reveal has to match the commitment
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_b")).unwrap_err();
+        assert_eq!(err, ContractError::CommitmentMismatch);
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(4321, b"salt_a")).unwrap_err();
+        assert_eq!(err, ContractError::CommitmentMismatch);
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_a")).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_a")).unwrap_err();
+        assert_eq!(err, ContractError::AlreadyRevealed);
+
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), roll.clone()).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForReveals);
+
+        // non-players can't reveal
+        let ted = mock_info("ted", &[]);
+        let err = execute(deps.as_mut(), env.clone(), ted, reveal_msg(5678, b"salt_b")).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+
+        let _res = execute(deps.as_mut(), env.clone(), bob, reveal_msg(5678, b"salt_b")).unwrap();
+
+        // the roll uses the revealed secrets, just like a regular game
+        let res = execute(deps.as_mut(), env.clone(), alice, roll).unwrap();
+
+        let mut regular_deps = mock_dependencies();
+        let _res = instantiate(regular_deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(regular_deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(regular_deps.as_mut(), env.clone(), bob, join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let regular_res = execute(regular_deps.as_mut(), env, alice, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        assert_eq!(res.attributes, regular_res.attributes);
+    }
+
+    #[test]
+    fn reveal_requires_commit_reveal_mode() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        // a commitment is not a secret
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), commit_msg("alice", 1234, b"salt")).unwrap_err();
+        assert_eq!(err, ContractError::MissingSecret);
+
+        let err = execute(deps.as_mut(), env, alice, reveal_msg(1234, b"salt")).unwrap_err();
+        assert_eq!(err, ContractError::NotACommitRevealGame);
+    }
+
+    #[test]
+    fn player_who_never_reveals_forfeits() {
+        let (mut deps, mut env) = commit_reveal_setup();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), commit_msg("alice", 1234, b"salt_a")).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), commit_msg("bob", 5678, b"salt_b")).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_a")).unwrap();
+
+        // bob stalls, but the deadline has to pass before alice can claim
+        let claim = ExecuteMsg::ClaimTimeout { game_id: GAME_ID };
+        env.block.height += 10;
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::DeadlineNotReached);
+
+        env.block.height += 1;
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), claim.clone()).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: coins(2_000_000, "uscrt"),
+        }));
+
+        // the game is over and alice won by forfeit
+        let err = execute(deps.as_mut(), env.clone(), bob, claim).unwrap_err();
+        assert_eq!(err, ContractError::GameIsAlreadyOver);
+
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap();
+        let value: WinnerResponse = from_binary(&res).unwrap();
+        assert_eq!(value.name, "alice");
+        assert_eq!(value.dice_roll, None);
+    }
+
+    #[test]
+    fn nobody_forfeits_while_everything_is_stopped() {
+        let (mut deps, mut env) = commit_reveal_setup();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), commit_msg("alice", 1234, b"salt_a")).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), commit_msg("bob", 5678, b"salt_b")).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), reveal_msg(1234, b"salt_a")).unwrap();
+
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        // bob never reveals, but alice only gets her own deposit back
+        env.block.height += 11;
+        let res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::ClaimTimeout { game_id: GAME_ID }).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|sub_msg| sub_msg.msg).collect();
+        assert_eq!(refunds, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "alice".to_string(), amount: coins(1_000_000, "uscrt") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bob".to_string(), amount: coins(1_000_000, "uscrt") }),
+        ]);
+
+        env.block.height += 1;
+        let err = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, StdError::generic_err("No winner yet."));
+    }
+
+    #[test]
+    fn nobody_reveals_and_both_are_refunded() {
+        let (mut deps, mut env) = commit_reveal_setup();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), commit_msg("alice", 1234, b"salt_a")).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), commit_msg("bob", 5678, b"salt_b")).unwrap();
+
+        env.block.height += 11;
+        let res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::ClaimTimeout { game_id: GAME_ID }).unwrap();
+        assert_eq!(res.messages.len(), 2);
+
+        // the game can be played again
+        let _res = execute(deps.as_mut(), env, alice, commit_msg("alice", 1234, b"salt_a")).unwrap();
+    }
+
+    #[test]
+    fn player_1_reclaims_deposit_after_join_timeout() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        let msg = InstantiateMsg { join_timeout: Some(Timeout::Blocks(5)), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let claim = ExecuteMsg::ClaimTimeout { game_id: GAME_ID };
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NothingToClaim);
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+
+        env.block.height += 5;
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::DeadlineNotReached);
+
+        // only player 1 can claim their deposit
+        env.block.height += 1;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), claim.clone()).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: coins(1_000_000, "uscrt"),
+        }));
+
+        // the game is gone, so the deposit can't be claimed twice
+        let err = execute(deps.as_mut(), env, alice, claim).unwrap_err();
+        assert_eq!(err, ContractError::NothingToClaim);
+    }
+
+    #[test]
+    fn timeouts_that_overflow_are_rejected() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        for timeout in [Timeout::Blocks(u64::MAX), Timeout::Seconds(u64::MAX / 1_000)] {
+            let msg = InstantiateMsg { join_timeout: Some(timeout), ..instantiate_msg() };
+            let err = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap_err();
+            assert!(matches!(err, StdError::Overflow { .. }));
+        }
+
+        // a deadline that only overflows later fails the join instead of panicking
+        let msg = InstantiateMsg { join_timeout: Some(Timeout::Blocks(u64::MAX - env.block.height)), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        env.block.height += 1;
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env, alice, join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::Overflow { .. })));
+    }
+
+    #[test]
+    fn players_are_refunded_after_roll_timeout() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+
+        let msg = InstantiateMsg { roll_timeout: Some(Timeout::Seconds(60)), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice, join_msg(GAME_ID, "alice", 1234)).unwrap();
+
+        // there is no join timeout, so player 1 has to leave instead
+        let claim = ExecuteMsg::ClaimTimeout { game_id: GAME_ID };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NothingToClaim);
+
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(60);
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), claim.clone()).unwrap_err();
+        assert_eq!(err, ContractError::DeadlineNotReached);
+
+        env.block.time = env.block.time.plus_seconds(1);
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), claim).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(refunds, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "alice".to_string(), amount: coins(1_000_000, "uscrt") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bob".to_string(), amount: coins(1_000_000, "uscrt") }),
+        ]);
+
+        // the dice can't be rolled for a refunded game
+        let err = execute(deps.as_mut(), env, bob, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForPlayers);
+    }
+
+    #[test]
+    fn leave_resets_the_game() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let leave = ExecuteMsg::Leave { game_id: GAME_ID };
+
+        // alice joins and leaves a few times, and is refunded exactly once per deposit
+        for _ in 0..3 {
+            let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+            let res = execute(deps.as_mut(), env.clone(), alice.clone(), leave.clone()).unwrap();
+            assert_eq!(res.messages.len(), 1);
+
+            let err = execute(deps.as_mut(), env.clone(), alice.clone(), leave.clone()).unwrap_err();
+            assert_eq!(err, ContractError::PlayerOneNotFound);
+        }
+
+        // the game record is fully cleared
+        let state = games_read(&deps.storage).may_load(&game_key(GAME_ID)).unwrap();
+        assert!(state.is_none());
+
+        // once alice left, bob is player 1 and alice can't leave in his place
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), leave.clone()).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+
+        // alice rejoins as player 2, and the game is played with the new seating
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: GAME_ID }).unwrap();
+        let value: WinnerResponse = from_binary(&res).unwrap();
+        let dice_roll = value.dice_roll.unwrap();
+        assert_eq!(value.name, if dice_roll <= 3 { "bob" } else { "alice" });
+
+        // nobody can leave a finished game
+        let err = execute(deps.as_mut(), env.clone(), bob, leave.clone()).unwrap_err();
+        assert_eq!(err, ContractError::GameIsAlreadyOver);
+        let err = execute(deps.as_mut(), env.clone(), alice, leave.clone()).unwrap_err();
+        assert_eq!(err, ContractError::GameIsAlreadyOver);
+        let err = execute(deps.as_mut(), env, mock_info("carol", &[]), leave).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+    }
+
+    #[test]
+    fn players_cancel_by_mutual_agreement() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let cancel = ExecuteMsg::Cancel { game_id: GAME_ID };
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+
+        // outsiders can't cancel
+        let err = execute(deps.as_mut(), env.clone(), mock_info("ted", &[]), cancel.clone()).unwrap_err();
+        assert_eq!(err, ContractError::YouAreNotAPlayer);
+
+        // player 2 asks first, nothing is paid yet
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), cancel.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(res.attributes[2].key, "requested_by");
+        assert_eq!(res.attributes[2].value, "bob");
+
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), cancel.clone()).unwrap_err();
+        assert_eq!(err, ContractError::CancellationAlreadyRequested);
+
+        // player 1 agrees and both are refunded
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), cancel.clone()).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(refunds, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "alice".to_string(), amount: coins(1_000_000, "uscrt") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bob".to_string(), amount: coins(1_000_000, "uscrt") }),
+        ]);
+        assert_eq!(res.attributes[2].key, "agreed_by");
+        assert_eq!(res.attributes[2].value, "alice");
+
+        // the game is back to a clean state
+        let state = games_read(&deps.storage).may_load(&game_key(GAME_ID)).unwrap();
+        assert!(state.is_none());
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), cancel.clone()).unwrap_err();
+        assert_eq!(err, ContractError::PlayerOneNotFound);
+
+        // a pending request doesn't stop the game from being played
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), cancel.clone()).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+
+        env.block.height += 1;
+        let err = execute(deps.as_mut(), env, bob, cancel).unwrap_err();
+        assert_eq!(err, ContractError::GameIsAlreadyOver);
+    }
+
+    #[test]
+    fn player_1_cancels_alone_before_player_2_joins() {
         let mut deps = mock_dependencies();
         let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
-
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
 
-        // Player 1 tries to roll the dice -- should produce an error
-        let err = execute(deps.as_mut(), env, info, ExecuteMsg::RollDice {}).unwrap_err();
-        match err {
-            ContractError::StillWaitingForPlayers {} => assert!(true),
-            _e => { assert!(false) }
-        }
+        let res = execute(deps.as_mut(), env, alice, ExecuteMsg::Cancel { game_id: GAME_ID }).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: coins(1_000_000, "uscrt"),
+        }));
     }
 
     #[test]
-    fn player_1_leaves_and_gets_refund() {
+    fn game_status() {
         let mut deps = mock_dependencies();
-        let env = mock_env();
+        let mut env = mock_env();
+        let msg = InstantiateMsg { join_timeout: Some(Timeout::Blocks(5)), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let status = |deps: Deps, env: Env| -> GameStatusResponse {
+            from_binary(&query(deps, env, QueryMsg::GameStatus { game_id: GAME_ID }).unwrap()).unwrap()
+        };
+
+        // a game nobody joined yet
+        assert_eq!(status(deps.as_ref(), env.clone()), GameStatusResponse {
+            state: ContractState::Init,
+            seats: 0,
+            dice: Dice { count: 1, sides: 6 },
+            players: vec![],
+            stake: Coin::new(1_000_000, "uscrt"),
+            deadline: None,
+            reveal_deadline: None,
+            cancel_requested_by: vec![],
+            winner_queryable: false,
+        });
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+
+        let value = status(deps.as_ref(), env.clone());
+        assert_eq!(value.state, ContractState::Open);
+        assert_eq!(value.seats, 2);
+        assert_eq!(value.players, vec![
+            PlayerResponse { name: "alice".to_string(), addr: Addr::unchecked("alice"), revealed: true },
+        ]);
+        assert_eq!(value.deadline, Some(Expiration::AtHeight(env.block.height + 5)));
+
+        let _res = execute(deps.as_mut(), env.clone(), bob, join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+
+        // the game is over, but the winner can only be queried from the next block on
+        let value = status(deps.as_ref(), env.clone());
+        assert_eq!(value.state, ContractState::Done);
+        assert_eq!(value.players.len(), 2);
+        assert!(!value.winner_queryable);
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 1;
+        assert!(status(deps.as_ref(), env.clone()).winner_queryable);
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        // secrets are never part of the response
+        let raw = query(deps.as_ref(), env, QueryMsg::GameStatus { game_id: GAME_ID }).unwrap();
+        let raw = String::from_utf8(raw.0).unwrap();
+        assert!(!raw.contains("secret") && !raw.contains("1234") && !raw.contains("5678"));
+    }
 
-        // Player 1 leaves the games and gets their 1 SCRT back
-        let _res = execute(deps.as_mut(), env, info, ExecuteMsg::Leave {}).unwrap();
+    fn play_game(mut deps: DepsMut, env: Env, game_id: u64, player_1: &str, player_2: &str) {
+        let info_1 = mock_info(player_1, &coins(1_000_000, "uscrt"));
+        let info_2 = mock_info(player_2, &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.branch(), env.clone(), info_1.clone(), join_msg(game_id, player_1, 1234)).unwrap();
+        let _res = execute(deps.branch(), env.clone(), info_2, join_msg(game_id, player_2, 5678)).unwrap();
+        let _res = execute(deps, env, info_1, ExecuteMsg::RollDice { game_id }).unwrap();
     }
 
     #[test]
-    fn leave_when_game_in_progress() {
+    fn game_history() {
         let mut deps = mock_dependencies();
-        let env = mock_env();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        play_game(deps.as_mut(), env.clone(), 7, "alice", "bob");
+        env.block.height += 1;
+        play_game(deps.as_mut(), env.clone(), 3, "carol", "dave");
+        env.block.height += 1;
+        play_game(deps.as_mut(), env.clone(), 9, "bob", "carol");
+
+        // games that aren't finished yet aren't archived
+        let info = mock_info("erin", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, join_msg(1, "erin", 1)).unwrap();
+
+        let history = |deps: Deps, env: Env, start_after: Option<u64>, limit: Option<u32>| -> GameHistoryResponse {
+            let msg = QueryMsg::GameHistory { start_after, limit };
+            from_binary(&query(deps, env, msg).unwrap()).unwrap()
+        };
+        let player_history = |deps: Deps, env: Env, address: &str, start_after: Option<u64>| -> MyGamesResponse {
+            let key = format!("{}'s key", address);
+            let msg = QueryMsg::MyGames { address: address.to_string(), key, start_after, limit: Some(1) };
+            from_binary(&query(deps, env, msg).unwrap()).unwrap()
+        };
+        for player in ["alice", "bob", "carol", "dave", "erin"] {
+            let msg = ExecuteMsg::SetViewingKey { key: format!("{}'s key", player) };
+            let _res = execute(deps.as_mut(), env.clone(), mock_info(player, &[]), msg).unwrap();
+        }
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info_player_1 = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info_player_1.clone(), msg).unwrap();
+        // the last game ended in the current block, so it isn't revealed yet
+        let value = history(deps.as_ref(), env.clone(), None, None);
+        assert_eq!(value.total, 3);
+        assert_eq!(value.games.iter().map(|game| game.game_id).collect::<Vec<u64>>(), vec![7, 3]);
 
-        // Player 2 joins the game
-        let secret = Uint128::new(5678u128);
-        let msg = ExecuteMsg::Join {name: "bob".to_string(), secret: secret};
-        let info_player_2 = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info_player_2, msg).unwrap();
+        env.block.height += 1;
+        let value = history(deps.as_ref(), env.clone(), None, None);
+        assert_eq!(value.games.iter().map(|game| game.index).collect::<Vec<u64>>(), vec![0, 1, 2]);
+
+        // the public history only names the players
+        let game = &value.games[0];
+        assert_eq!(game.players, vec!["alice".to_string(), "bob".to_string()]);
+        assert_eq!(game.stake, Coin::new(1_000_000, "uscrt"));
+        assert_eq!(game.block_height, mock_env().block.height);
+        assert_eq!(game.timestamp, mock_env().block.time);
+        assert!(game.players.contains(&game.winner));
+
+        // the archived record matches the game's result
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: 7 }).unwrap();
+        let winner: WinnerResponse = from_binary(&res).unwrap();
+        assert_eq!(game.winner, winner.name);
+        assert_eq!(game.dice_roll, winner.dice_roll);
+
+        // pagination
+        let value = history(deps.as_ref(), env.clone(), None, Some(2));
+        assert_eq!(value.games.iter().map(|game| game.index).collect::<Vec<u64>>(), vec![0, 1]);
+        let value = history(deps.as_ref(), env.clone(), Some(1), Some(2));
+        assert_eq!(value.games.iter().map(|game| game.index).collect::<Vec<u64>>(), vec![2]);
+        let value = history(deps.as_ref(), env.clone(), Some(2), None);
+        assert!(value.games.is_empty());
+
+        // per player, only with their key
+        let value = player_history(deps.as_ref(), env.clone(), "bob", None);
+        assert_eq!(value.total, 2);
+        assert_eq!(value.games[0].game_id, 7);
+        assert_eq!(value.games[0].players, vec![
+            PlayerRecord { name: "alice".to_string(), addr: Addr::unchecked("alice") },
+            PlayerRecord { name: "bob".to_string(), addr: Addr::unchecked("bob") },
+        ]);
+        let value = player_history(deps.as_ref(), env.clone(), "bob", Some(value.games[0].index));
+        assert_eq!(value.games[0].game_id, 9);
+        let value = player_history(deps.as_ref(), env.clone(), "bob", Some(value.games[0].index));
+        assert!(value.games.is_empty());
+
+        let value = player_history(deps.as_ref(), env.clone(), "carol", Some(0));
+        assert_eq!(value.games.iter().map(|game| game.game_id).collect::<Vec<u64>>(), vec![3]);
+
+        let value = player_history(deps.as_ref(), env, "erin", None);
+        assert_eq!(value.total, 0);
+        assert!(value.games.is_empty());
+    }
 
-        // Player 1 tries to leave the game, but it's in progress, awaiting a dice roll
-        let err = execute(deps.as_mut(), env, info_player_1, ExecuteMsg::Leave {}).unwrap_err();
-        match err {
-            ContractError::GameIsInProgress {} => assert!(true),
-            e => {
-                assert!(false);
-                panic!("error: {}", e);
+    #[test]
+    fn player_history_needs_their_key() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+        play_game(deps.as_mut(), env.clone(), 7, "alice", "bob");
+        env.block.height += 1;
+
+        // there is no unauthenticated query for the games of an address
+        let msg = br#"{"player_history":{"address":"alice"}}"#;
+        assert!(from_slice::<QueryMsg>(msg).is_err());
+
+        let my_games = |key: &str| QueryMsg::MyGames {
+            address: "alice".to_string(),
+            key: key.to_string(),
+            start_after: None,
+            limit: None,
+        };
+        let wrong_key = StdError::generic_err("Wrong viewing key for this address or viewing key not set.");
+        assert_eq!(query(deps.as_ref(), env.clone(), my_games("")).unwrap_err(), wrong_key);
+
+        let msg = ExecuteMsg::SetViewingKey { key: "bob's key".to_string() };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        assert_eq!(query(deps.as_ref(), env.clone(), my_games("bob's key")).unwrap_err(), wrong_key);
+
+        // and neither the public history nor the winner of a game give the addresses away
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::GameHistory { start_after: None, limit: None }).unwrap();
+        assert!(!String::from_utf8(res.0).unwrap().contains(r#""addr""#));
+        let res = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: 7 }).unwrap();
+        assert!(!String::from_utf8(res.0).unwrap().contains(r#""addr""#));
+    }
+
+    #[test]
+    fn viewing_keys_gate_private_queries() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        // alice creates a key, bob picks his own
+        let msg = ExecuteMsg::CreateViewingKey { entropy: "alice's entropy".to_string() };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let alice_key = match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            answer => panic!("unexpected answer: {:?}", answer),
+        };
+        let msg = ExecuteMsg::SetViewingKey { key: "bob's key".to_string() };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+
+        let balance = |deps: Deps, address: &str, key: &str| -> StdResult<MyBalanceResponse> {
+            let msg = QueryMsg::MyBalance { address: address.to_string(), key: key.to_string() };
+            from_binary(&query(deps, mock_env(), msg)?)
+        };
+        let wrong_key = StdError::generic_err("Wrong viewing key for this address or viewing key not set.");
+
+        // only the right key for the right address works
+        assert_eq!(balance(deps.as_ref(), "alice", "bob's key").unwrap_err(), wrong_key);
+        assert_eq!(balance(deps.as_ref(), "bob", &alice_key).unwrap_err(), wrong_key);
+        assert_eq!(balance(deps.as_ref(), "carol", "").unwrap_err(), wrong_key);
+
+        assert_eq!(balance(deps.as_ref(), "alice", &alice_key).unwrap(), MyBalanceResponse {
+            pending: Coin::new(0, "uscrt"),
+            game_ids: vec![],
+        });
+
+        // deposits are pending until the game ends
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(1, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(2, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(1, "bob", 5678)).unwrap();
+
+        assert_eq!(balance(deps.as_ref(), "alice", &alice_key).unwrap(), MyBalanceResponse {
+            pending: Coin::new(2_000_000, "uscrt"),
+            game_ids: vec![1, 2],
+        });
+        assert_eq!(balance(deps.as_ref(), "bob", "bob's key").unwrap().game_ids, vec![1]);
+
+        let _res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::RollDice { game_id: 1 }).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::Leave { game_id: 2 }).unwrap();
+
+        assert_eq!(balance(deps.as_ref(), "alice", &alice_key).unwrap().pending, Coin::new(0, "uscrt"));
+        assert!(balance(deps.as_ref(), "bob", "bob's key").unwrap().game_ids.is_empty());
+
+        // the history of a player requires their key too
+        env.block.height += 1;
+        let my_games = |address: &str, key: &str| QueryMsg::MyGames {
+            address: address.to_string(),
+            key: key.to_string(),
+            start_after: None,
+            limit: None,
+        };
+        let err = query(deps.as_ref(), env.clone(), my_games("alice", "bob's key")).unwrap_err();
+        assert_eq!(err, wrong_key);
+
+        let res = query(deps.as_ref(), env, my_games("alice", &alice_key)).unwrap();
+        let value: MyGamesResponse = from_binary(&res).unwrap();
+        assert_eq!(value.games.len(), 1);
+        assert_eq!(value.games[0].game_id, 1);
+    }
+
+    #[test]
+    fn permits_gate_private_queries() {
+        // a permit for the `history` permission, signed by `signer` for the contract `token`
+        let token = "secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq";
+        let signer = "secret1399pyvvk3hvwgxwt3udkslsc5jl3rqv4yshfrl";
+        let permit: Permit = from_binary(&Binary::from(br#"{
+            "params": {
+                "chain_id": "pulsar-2",
+                "permit_name": "memo_secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq",
+                "allowed_tokens": ["secret1rf03820fp8gngzg2w02vd30ns78qkc8rg8dxaq"],
+                "permissions": ["history"]
             },
-        }
+            "signature": {
+                "pub_key": {
+                    "type": "tendermint/PubKeySecp256k1",
+                    "value": "A5M49l32ZrV+SDsPnoRv8fH7ivNC4gEX9prvd4RwvRaL"
+                },
+                "signature": "hw/Mo3ZZYu1pEiDdymElFkuCuJzg9soDHw+4DxK7cL9rafiyykh7VynS+guotRAKXhfYMwCiyWmiznc6R+UlsQ=="
+            }
+        }"#.to_vec())).unwrap();
+
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        env.contract.address = Addr::unchecked(token);
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let signer_info = mock_info(signer, &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), signer_info, join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        env.block.height += 1;
+
+        let with_permit = |query: QueryWithPermit| QueryMsg::WithPermit { permit: permit.clone(), query };
+        let my_games = with_permit(QueryWithPermit::MyGames { start_after: None, limit: None });
+
+        let res = query(deps.as_ref(), env.clone(), my_games.clone()).unwrap();
+        let value: MyGamesResponse = from_binary(&res).unwrap();
+        assert_eq!(value.total, 1);
+        assert_eq!(value.games[0].game_id, GAME_ID);
+        assert!(value.games[0].players.iter().any(|player| player.addr == signer));
+
+        // the permit only grants the history permission
+        let err = query(deps.as_ref(), env.clone(), with_permit(QueryWithPermit::MyBalance {})).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("The permit does not grant the Balance permission, got [History]")
+        );
+
+        // nor is it valid for another contract
+        let err = query(deps.as_ref(), mock_env(), my_games.clone()).unwrap_err();
+        assert!(err.to_string().contains("Permit doesn't apply to token"));
+
+        // a tampered permit is rejected
+        let mut tampered = permit.clone();
+        tampered.params.permissions.push(TokenPermissions::Balance);
+        let msg = QueryMsg::WithPermit { permit: tampered, query: QueryWithPermit::MyBalance {} };
+        let err = query(deps.as_ref(), env.clone(), msg).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Failed to verify signatures for the given permit"));
+
+        // revoking someone else's permit has no effect, the signer's revocation does
+        let msg = ExecuteMsg::RevokePermit { permit_name: permit.params.permit_name.clone() };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg.clone()).unwrap();
+        let _res = query(deps.as_ref(), env.clone(), my_games.clone()).unwrap();
+
+        let _res = execute(deps.as_mut(), env.clone(), mock_info(signer, &[]), msg).unwrap();
+        let err = query(deps.as_ref(), env, my_games).unwrap_err();
+        assert!(err.to_string().contains("was revoked by account"));
     }
 
     #[test]
-    fn not_a_player() {
+    fn admin_role() {
         let mut deps = mock_dependencies();
         let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let status = |deps: Deps| -> ContractStatusResponse {
+            from_binary(&query(deps, mock_env(), QueryMsg::ContractStatus {}).unwrap()).unwrap()
+        };
+        assert_eq!(status(deps.as_ref()), ContractStatusResponse {
+            admin: Addr::unchecked("creator"),
+            status: ContractStatus::Normal,
+        });
+
+        // only the admin can hand over the role or change the status
+        let change_admin = ExecuteMsg::ChangeAdmin { address: "alice".to_string() };
+        let stop = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), change_admin.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), stop.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), change_admin).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), stop.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let _res = execute(deps.as_mut(), env, mock_info("alice", &[]), stop).unwrap();
+
+        assert_eq!(status(deps.as_ref()), ContractStatusResponse {
+            admin: Addr::unchecked("alice"),
+            status: ContractStatus::StopAll,
+        });
+
+        // the admin can also be chosen at instantiation
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { admin: Some("bob".to_string()), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(status(deps.as_ref()).admin, Addr::unchecked("bob"));
+    }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    #[test]
+    fn kill_switch() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let admin = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), env.clone(), admin.clone(), instantiate_msg()).unwrap();
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(1, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(1, "bob", 5678)).unwrap();
+
+        // games in progress can still be played when joins are stopped
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopJoins };
+        let _res = execute(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(2, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::JoinsAreStopped);
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), ExecuteMsg::RollDice { game_id: 1 }).unwrap();
+
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::Normal };
+        let _res = execute(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(2, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(2, "bob", 5678)).unwrap();
+
+        // nothing can be played once all is stopped
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let _res = execute(deps.as_mut(), env.clone(), admin, msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), ExecuteMsg::RollDice { game_id: 2 }).unwrap_err();
+        assert_eq!(err, ContractError::ContractIsStopped);
+        let err = execute(deps.as_mut(), env, bob, join_msg(3, "bob", 5678)).unwrap_err();
+        assert_eq!(err, ContractError::JoinsAreStopped);
+    }
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+    #[test]
+    fn emergency_refund() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let admin = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), env.clone(), admin.clone(), instantiate_msg()).unwrap();
+
+        // game 1 is full, game 2 waits for an opponent, game 3 is over and game 4 was left
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(1, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(1, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(2, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(3, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(3, "bob", 5678)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), ExecuteMsg::RollDice { game_id: 3 }).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(4, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), ExecuteMsg::Leave { game_id: 4 }).unwrap();
+
+        // only possible for the admin, once everything is stopped
+        let refund = |limit: Option<u32>| ExecuteMsg::EmergencyRefund { limit };
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), refund(None)).unwrap_err();
+        assert_eq!(err, ContractError::ContractIsNotStopped);
+
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let _res = execute(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), alice, refund(None)).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+
+        // refunds can be spread over several transactions, the latest open game first
+        assert_eq!(open_game_count_read(&deps.storage).load().unwrap(), 2);
+        let res = execute(deps.as_mut(), env.clone(), admin.clone(), refund(Some(1))).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(refunds, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bob".to_string(), amount: coins(1_000_000, "uscrt") }),
+        ]);
+        assert_eq!(res.attributes[2].value, "1");
+        assert_eq!(res.attributes[2].key, "remaining_games");
+
+        let res = execute(deps.as_mut(), env.clone(), admin.clone(), refund(None)).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(refunds, vec![
+            CosmosMsg::Bank(BankMsg::Send { to_address: "alice".to_string(), amount: coins(1_000_000, "uscrt") }),
+            CosmosMsg::Bank(BankMsg::Send { to_address: "bob".to_string(), amount: coins(1_000_000, "uscrt") }),
+        ]);
+        assert_eq!(open_game_count_read(&deps.storage).load().unwrap(), 0);
+
+        // every deposit was paid back exactly once
+        let res = execute(deps.as_mut(), env, admin, refund(None)).unwrap();
+        assert!(res.messages.is_empty());
+        for game_id in [1, 2] {
+            assert!(games_read(&deps.storage).may_load(&game_key(game_id)).unwrap().is_none());
+        }
+        assert_eq!(
+            games_read(&deps.storage).load(&game_key(3)).unwrap().state,
+            ContractState::Done
+        );
+        assert!(player_games_read(&deps.storage).may_load(b"bob").unwrap().is_none());
+    }
 
-        // Player 2 joins the game
-        let secret = Uint128::new(5678u128);
-        let msg = ExecuteMsg::Join {name: "bob".to_string(), secret: secret};
-        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    #[test]
+    fn open_games_stay_packed() {
+        let mut deps = mock_dependencies();
+        let open_game_ids = |storage: &dyn Storage| -> Vec<u64> {
+            let count = open_game_count_read(storage).may_load().unwrap().unwrap_or_default();
+            (0..count).map(|position| open_games_read(storage).load(&position.to_be_bytes()).unwrap()).collect()
+        };
 
-        // Non-player tries to interact with the game
-        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
-        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Leave {}).unwrap_err();
-        match err {
-            ContractError::YouAreNotAPlayer {} => assert!(true),
-            e => {
-                assert!(false);
-                panic!("error: {}", e);
-            },
+        for game_id in [1, 2, 3, 4] {
+            add_open_game(&mut deps.storage, game_id).unwrap();
         }
+        add_open_game(&mut deps.storage, 2).unwrap();
+        assert_eq!(open_game_ids(&deps.storage), vec![1, 2, 3, 4]);
+
+        // the last game takes the place of the one removed
+        remove_open_game(&mut deps.storage, 2).unwrap();
+        assert_eq!(open_game_ids(&deps.storage), vec![1, 4, 3]);
+        remove_open_game(&mut deps.storage, 3).unwrap();
+        remove_open_game(&mut deps.storage, 3).unwrap();
+        assert_eq!(open_game_ids(&deps.storage), vec![1, 4]);
+        remove_open_game(&mut deps.storage, 1).unwrap();
+        remove_open_game(&mut deps.storage, 4).unwrap();
+        assert!(open_game_ids(&deps.storage).is_empty());
+        assert!(deps.storage.get(&[to_length_prefixed(b"open_games").as_slice(), &0u64.to_be_bytes()].concat()).is_none());
+    }
 
-        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
-        let err = execute(deps.as_mut(), env, info, ExecuteMsg::RollDice {}).unwrap_err();
-        match err {
-            ContractError::YouAreNotAPlayer {} => assert!(true),
-            e => {
-                assert!(false);
-                panic!("error: {}", e);
-            },
-        }
+    #[test]
+    fn house_fee_is_rounded_down() {
+        let fee = |pot: u128, fee_bps: u16| house_fee(Uint128::new(pot), fee_bps).u128();
+
+        assert_eq!(fee(2_000_000, 0), 0);
+        assert_eq!(fee(2_000_000, 250), 50_000);
+        // 50_000.05
+        assert_eq!(fee(2_000_002, 250), 50_000);
+        // 1.99 and 0.99
+        assert_eq!(fee(199, 100), 1);
+        assert_eq!(fee(99, 100), 0);
+        assert_eq!(fee(2, 9_999), 1);
+        // the largest fee still leaves something for the winner
+        assert_eq!(fee(2_000_000, 9_999), 1_999_800);
+        assert!(fee(u128::MAX, 9_999) < u128::MAX);
     }
 
     #[test]
-    fn game_is_full() {
+    fn house_fee_is_kept_from_the_pot() {
         let mut deps = mock_dependencies();
         let env = mock_env();
+        let admin = mock_info("creator", &[]);
+
+        // the pot or a winning bet can't be kept whole by the house
+        for msg in [
+            InstantiateMsg { fee_bps: Some(10_000), ..instantiate_msg() },
+            InstantiateMsg { house_edge_bps: Some(10_000), ..instantiate_msg() },
+        ] {
+            let err = instantiate(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap_err();
+            assert_eq!(err, StdError::generic_err("The fee must be less than 10000 basis points."));
+        }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msg = InstantiateMsg { stake: Coin::new(1_000_001, "uscrt"), fee_bps: Some(250), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap();
+
+        // 2.5% of the 2_000_002 pot is 50_000.05, the house keeps 50_000
+        let alice = mock_info("alice", &coins(1_000_001, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_001, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob, join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), alice, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => {
+                assert_eq!(amount, &coins(1_950_002, "uscrt"));
+            },
+            msg => panic!("unexpected message: {:?}", msg),
+        }
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let fees = |deps: Deps| -> FeesResponse {
+            from_binary(&query(deps, mock_env(), QueryMsg::Fees {}).unwrap()).unwrap()
+        };
+        assert_eq!(fees(deps.as_ref()), FeesResponse {
+            fee_bps: 250,
+            accrued: Coin::new(50_000, "uscrt"),
+        });
+
+        // only the admin can withdraw, and no more than what was accrued
+        let withdraw = |amount: u128| ExecuteMsg::WithdrawFees {
+            recipient: "treasurer".to_string(),
+            amount: Uint128::new(amount),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), withdraw(20_000)).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), withdraw(50_001)).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientFees(Coin::new(50_000, "uscrt")));
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), withdraw(0)).unwrap_err();
+        assert_eq!(err, ContractError::ZeroWithdrawal);
+
+        let res = execute(deps.as_mut(), env.clone(), admin.clone(), withdraw(20_000)).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "treasurer".to_string(),
+            amount: coins(20_000, "uscrt"),
+        }));
+        assert_eq!(fees(deps.as_ref()).accrued, Coin::new(30_000, "uscrt"));
+
+        let _res = execute(deps.as_mut(), env.clone(), admin.clone(), withdraw(30_000)).unwrap();
+        assert_eq!(fees(deps.as_ref()).accrued, Coin::new(0, "uscrt"));
+        let err = execute(deps.as_mut(), env, admin, withdraw(1)).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientFees(Coin::new(0, "uscrt")));
+    }
 
-        // Player 2 joins the game
-        let secret = Uint128::new(5678u128);
-        let msg = ExecuteMsg::Join {name: "bob".to_string(), secret: secret};
-        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    #[test]
+    fn games_with_more_seats() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
 
-        // A 3rd player tries to join
-        let secret = Uint128::new(9810u128);
-        let info = mock_info("ted", &coins(1_000_000, "uscrt"));
-        let msg = ExecuteMsg::Join {name: "ted".to_string(), secret: secret};
-        let err = execute(deps.as_mut(), env, info, msg).unwrap_err();
-        match err {
-            ContractError::GameIsFull {} => assert!(true),
-            e => {
-                assert!(false);
-                panic!("error: {}", e);
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let carol = mock_info("carol", &coins(1_000_000, "uscrt"));
+
+        // a game needs at least 2 players, and can't have more seats than the die has faces
+        for seats in [0, 1, 7] {
+            let err = execute(deps.as_mut(), env.clone(), alice.clone(), join_seats_msg(GAME_ID, "alice", 1234, seats)).unwrap_err();
+            assert_eq!(err, ContractError::InvalidSeats(6));
+        }
+
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_seats_msg(GAME_ID, "alice", 1234, 3)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap_err();
+        assert_eq!(err, ContractError::AlreadyJoined);
+
+        // the seat count was chosen by the first player
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), join_seats_msg(GAME_ID, "bob", 5678, 2)).unwrap_err();
+        assert_eq!(err, ContractError::OnlyCreatorChoosesSettings);
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap_err();
+        assert_eq!(err, ContractError::StillWaitingForPlayers);
+
+        let _res = execute(deps.as_mut(), env.clone(), carol, join_msg(GAME_ID, "carol", 9012)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info("dave", &coins(1_000_000, "uscrt")), join_msg(GAME_ID, "dave", 3456)).unwrap_err();
+        assert_eq!(err, ContractError::GameIsFull);
+
+        // the winner takes the three deposits
+        let res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => {
+                assert_eq!(amount, &coins(3_000_000, "uscrt"));
             },
+            msg => panic!("unexpected message: {:?}", msg),
         }
+
+        // each player has two faces of the die
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap();
+        let value: WinnerResponse = from_binary(&res).unwrap();
+        let seat = (value.dice_roll.unwrap() - 1) / 2;
+        assert_eq!(value.name, ["alice", "bob", "carol"][seat as usize]);
     }
 
     #[test]
-    fn no_winner_yet() {
+    fn players_leave_an_open_game_and_cancel_a_full_one_together() {
         let mut deps = mock_dependencies();
         let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let carol = mock_info("carol", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_seats_msg(GAME_ID, "alice", 1234, 3)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 5678)).unwrap();
+
+        // any player can leave before the game is full, the others keep their seats
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), ExecuteMsg::Leave { game_id: GAME_ID }).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: coins(1_000_000, "uscrt"),
+        }));
+        let state = games_read(&deps.storage).load(&game_key(GAME_ID)).unwrap();
+        assert_eq!(state.state, ContractState::Open);
+        assert!(state.is_player(&Addr::unchecked("bob")));
+        assert!(!state.is_player(&Addr::unchecked("alice")));
+
+        let _res = execute(deps.as_mut(), env.clone(), carol.clone(), join_msg(GAME_ID, "carol", 9012)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join_msg(GAME_ID, "alice", 1234)).unwrap();
+
+        // once it is full, all the players have to agree to cancel
+        let cancel = ExecuteMsg::Cancel { game_id: GAME_ID };
+        let res = execute(deps.as_mut(), env.clone(), bob, cancel.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        let res = execute(deps.as_mut(), env.clone(), alice, cancel.clone()).unwrap();
+        assert!(res.messages.is_empty());
+
+        let res = execute(deps.as_mut(), env, carol, cancel).unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.into_iter().map(|msg| msg.msg).collect();
+        assert_eq!(refunds, ["bob", "carol", "alice"].iter().map(|player| {
+            CosmosMsg::Bank(BankMsg::Send { to_address: player.to_string(), amount: coins(1_000_000, "uscrt") })
+        }).collect::<Vec<CosmosMsg>>());
+        assert!(games_read(&deps.storage).may_load(&game_key(GAME_ID)).unwrap().is_none());
+    }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+    #[test]
+    fn players_who_revealed_play_without_the_others() {
+        let (mut deps, mut env) = commit_reveal_setup();
+        let players = [("alice", 1234), ("bob", 5678), ("carol", 9012)];
+
+        for (name, secret) in players {
+            let commitment = secret_commitment(&Uint128::new(secret), &Binary::from(name.as_bytes()));
+            let msg = ExecuteMsg::Join {
+                game_id: GAME_ID,
+                name: name.to_string(),
+                seats: (name == "alice").then_some(3),
+                dice: None,
+                secret: None,
+                commitment: Some(Binary::from(commitment.to_vec())),
+                callback: None,
+            };
+            let _res = execute(deps.as_mut(), env.clone(), mock_info(name, &coins(1_000_000, "uscrt")), msg).unwrap();
+        }
+        for (name, secret) in &players[..2] {
+            let msg = reveal_msg(*secret, name.as_bytes());
+            let _res = execute(deps.as_mut(), env.clone(), mock_info(name, &[]), msg).unwrap();
+        }
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        // carol never reveals, so alice and bob roll for the whole pot
+        env.block.height += 11;
+        let claim = ExecuteMsg::ClaimTimeout { game_id: GAME_ID };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), claim).unwrap();
+        let to_address = match &res.messages[0].msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                assert_eq!(amount, &coins(3_000_000, "uscrt"));
+                to_address.clone()
+            },
+            msg => panic!("unexpected message: {:?}", msg),
+        };
 
-        // Player 2 joins the game
-        let secret = Uint128::new(5678u128);
-        let msg = ExecuteMsg::Join {name: "bob".to_string(), secret: secret};
-        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env, QueryMsg::WhoWon { game_id: GAME_ID }).unwrap();
+        let value: WinnerResponse = from_binary(&res).unwrap();
+        assert_eq!(value.name, to_address);
+        assert_eq!(value.name, if value.dice_roll.unwrap() <= 3 { "alice" } else { "bob" });
+    }
 
-        // there should be no winner yet since we didn't do a dice roll!
-        let err = query(deps.as_ref(), env, QueryMsg::WhoWon {}).unwrap_err();
-        match err {
-            _ => { assert!(true) }
+    #[test]
+    fn every_seat_has_the_same_chance() {
+        const ROLLS: u32 = 60_000;
+
+        let d6 = Dice::default();
+        let mut games: Vec<(Dice, u8)> = (2..=6).map(|seats| (d6.clone(), seats)).collect();
+        games.push((Dice { count: 1, sides: 20 }, 7));
+        games.push((Dice { count: 2, sides: 6 }, 2));
+        games.push((Dice { count: 3, sides: 10 }, 2));
+
+        for (dice, seats) in games {
+            let mut rng = ChaChaRng::from_seed([seats + dice.count + dice.sides; 32]);
+            let mut wins = vec![0u32; seats as usize];
+
+            for _ in 0..ROLLS {
+                let (dice_faces, seat) = roll_for_seat(&mut rng, &dice, seats);
+                assert_eq!(dice_faces.len(), dice.count as usize);
+                assert!(dice_faces.iter().all(|face| (1..=dice.sides).contains(face)));
+                wins[seat] += 1;
+            }
+
+            // within 5% of a fair share
+            let fair_share = ROLLS / seats as u32;
+            for seat_wins in wins {
+                assert!(
+                    seat_wins.abs_diff(fair_share) < fair_share / 20,
+                    "{}d{} with {} seats: {}", dice.count, dice.sides, seats, seat_wins,
+                );
+            }
+        }
+
+        // with 2 seats, 1-3 is player 1 and 4-6 player 2
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        for _ in 0..100 {
+            let (dice_faces, seat) = roll_for_seat(&mut rng, &d6, 2);
+            assert_eq!(seat, if dice_faces[0] <= 3 { 0 } else { 1 });
+        }
+
+        // with 2d6, 2-6 is player 1, 8-12 player 2 and 7 is rolled again
+        let two_d6 = Dice { count: 2, sides: 6 };
+        for _ in 0..100 {
+            let (dice_faces, seat) = roll_for_seat(&mut rng, &two_d6, 2);
+            let total = dice_faces[0] + dice_faces[1];
+            assert_ne!(total, 7);
+            assert_eq!(seat, if total < 7 { 0 } else { 1 });
         }
     }
 
     #[test]
-    fn query_not_allowed_in_same_block() {
+    fn games_with_other_dice() {
         let mut deps = mock_dependencies();
-        let env = mock_env();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let join = |game_id: u64, name: &str, seats: Option<u8>, dice: Option<Dice>| ExecuteMsg::Join {
+            game_id,
+            name: name.to_string(),
+            seats,
+            dice,
+            secret: Some(Uint128::new(name.len() as u128)),
+            commitment: None,
+            callback: None,
+        };
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+
+        // the dice have limits
+        for dice in [Dice { count: 0, sides: 6 }, Dice { count: 11, sides: 6 }, Dice { count: 1, sides: 1 }, Dice { count: 1, sides: 101 }] {
+            let err = execute(deps.as_mut(), env.clone(), alice.clone(), join(1, "alice", None, Some(dice))).unwrap_err();
+            assert_eq!(err, ContractError::InvalidDice { count: 10, sides: 100 });
+        }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        // a d20 can be shared by up to 20 players, but the total of several dice only by 2
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), join(1, "alice", Some(21), Some(Dice { count: 1, sides: 20 }))).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSeats(20));
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), join(1, "alice", Some(3), Some(Dice { count: 2, sides: 6 }))).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSeats(2));
 
-        // Player 1 joins the game
-        let secret_1 = Uint128::new(1234u128);
-        let msg_player_1 = ExecuteMsg::Join {name: "alice".to_string(), secret: secret_1};
-        let info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg_player_1).unwrap();
+        // the dice are chosen by the first player, bob can't choose others
+        let three_d10 = Dice { count: 3, sides: 10 };
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), join(1, "alice", None, Some(three_d10.clone()))).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), join(1, "bob", None, Some(Dice { count: 1, sides: 20 }))).unwrap_err();
+        assert_eq!(err, ContractError::OnlyCreatorChoosesSettings);
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join(1, "bob", None, None)).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::RollDice { game_id: 1 }).unwrap();
 
-        // Player 2 joins the game
-        let secret_2 = Uint128::new(5678u128);
-        let msg_player_2 = ExecuteMsg::Join {name: "bob".to_string(), secret: secret_2};
-        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg_player_2).unwrap();
+        env.block.height += 1;
+        let res_query = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: 1 }).unwrap();
+        let value: WinnerResponse = from_binary(&res_query).unwrap();
+        assert_eq!(value.dice_faces.len(), 3);
+        assert!(value.dice_faces.iter().all(|face| (1..=10).contains(face)));
+        let total: u16 = value.dice_faces.iter().map(|face| *face as u16).sum();
+        assert_eq!(value.dice_roll, Some(total));
+        assert_eq!(res.attributes[2].value, total.to_string());
+
+        // 3-16 is player 1, 17-30 player 2
+        assert_eq!(value.name, if total * 2 < 33 { "alice" } else { "bob" });
+
+        let res = query(deps.as_ref(), env, QueryMsg::GameStatus { game_id: 1 }).unwrap();
+        let value: GameStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(value.dice, three_d10);
+    }
 
-        // Player 2 rolls the dice
-        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::RollDice {}).unwrap();
+    #[test]
+    fn bet_odds() {
+        let d6 = Dice::default();
+        let two_d6 = Dice { count: 2, sides: 6 };
+        let three_d10 = Dice { count: 3, sides: 10 };
+
+        assert_eq!(count_outcomes(&d6, &Prediction::Over(3)), (3, 6));
+        assert_eq!(count_outcomes(&d6, &Prediction::Exact(6)), (1, 6));
+        assert_eq!(count_outcomes(&d6, &Prediction::Under(1)), (0, 6));
+        assert_eq!(count_outcomes(&two_d6, &Prediction::Exact(7)), (6, 36));
+        assert_eq!(count_outcomes(&two_d6, &Prediction::Odd), (18, 36));
+        assert_eq!(count_outcomes(&two_d6, &Prediction::Over(9)), (6, 36));
+        assert_eq!(count_outcomes(&three_d10, &Prediction::Under(4)), (1, 1000));
+        assert_eq!(count_outcomes(&three_d10, &Prediction::Even), (500, 1000));
+        assert_eq!(count_outcomes(&Dice { count: 10, sides: 100 }, &Prediction::Over(0)).1, 100u128.pow(10));
+    }
 
-        // should result in an error because execute and query on winner cannot be done in the same block height
-        let err = query(deps.as_ref(), env, QueryMsg::WhoWon {}).unwrap_err();
-        match err {
-            _ => {
-                assert!(true);
-            }
+    #[test]
+    fn house_seed_needs_private_entropy() {
+        let house_seed = |env: Env, msg: InstantiateMsg| -> StdResult<Vec<u8>> {
+            let mut deps = mock_dependencies();
+            instantiate(deps.as_mut(), env, mock_info("creator", &[]), msg)?;
+            house_seed_read(&deps.storage).load()
+        };
+        let with_seed = |prng_seed: &[u8]| InstantiateMsg { prng_seed: Some(Binary::from(prng_seed)), ..instantiate_msg() };
+        let without_seed = || InstantiateMsg { prng_seed: None, ..instantiate_msg() };
+
+        // the block and the creator are public, the entropy is what tells the seeds apart
+        assert_ne!(house_seed(mock_env(), with_seed(b"entropy")).unwrap(), house_seed(mock_env(), with_seed(b"other")).unwrap());
+
+        // the chain's randomness is enough on its own
+        #[cfg(feature = "random")]
+        {
+            let mut env = mock_env();
+            env.block.random = Some(Binary::from(b"other randomness"));
+            assert_ne!(house_seed(mock_env(), without_seed()).unwrap(), house_seed(env, without_seed()).unwrap());
         }
+
+        // without either, anyone could compute the house seed and win every bet
+        let env = Env {
+            block: BlockInfo {
+                #[cfg(feature = "random")]
+                random: None,
+                ..mock_env().block
+            },
+            ..mock_env()
+        };
+        let err = house_seed(env.clone(), without_seed()).unwrap_err();
+        assert_eq!(err, StdError::generic_err("A prng_seed is needed to take bets."));
+
+        // which doesn't matter in commit-reveal mode, where there are no bets
+        let msg = InstantiateMsg { commit_reveal: Some(CommitRevealConfig { reveal_timeout: 10 }), ..without_seed() };
+        assert!(house_seed(env, msg).is_ok());
     }
 
     #[test]
-    fn roll_the_dice() {
+    fn bets_against_the_house() {
         let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        let uscrt_denom = "uscrt".to_string();
+        let env = mock_env();
+        let msg = InstantiateMsg { house_edge_bps: Some(100), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let bet = |prediction: Prediction, dice: Option<Dice>, secret: u128| ExecuteMsg::Bet {
+            prediction,
+            dice,
+            secret: Uint128::new(secret),
+        };
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+
+        // the house has to be able to pay the winnings
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), bet(Prediction::Over(3), None, 1)).unwrap_err();
+        assert_eq!(err, ContractError::ExposureLimitExceeded(Coin::new(0, "uscrt")));
+        let info = mock_info("creator", &coins(100_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap();
+
+        // bets that can't be lost or won are pointless
+        let two_d6 = Some(Dice { count: 2, sides: 6 });
+        for (prediction, dice) in [(Prediction::Over(6), None), (Prediction::Under(7), None), (Prediction::Exact(1), two_d6)] {
+            let err = execute(deps.as_mut(), env.clone(), alice.clone(), bet(prediction, dice, 1)).unwrap_err();
+            assert_eq!(err, ContractError::InvalidPrediction);
+        }
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &coins(10, "uscrt")), bet(Prediction::Odd, None, 1)).unwrap_err();
+        assert_eq!(err, ContractError::MustDepositStakeToPlay(Coin::new(1_000_000, "uscrt")));
+
+        // a 1 in 2 bet pays twice the stake minus the 1% edge, and 7 with 2d6 six times the stake
+        let mut next_block = env.clone();
+        next_block.block.height += 1;
+        let mut later_block = env.clone();
+        later_block.block.height += 2;
+        let outcome = |deps: Deps, bet_id: u64| -> BetOutcome {
+            let res = query(deps, later_block.clone(), QueryMsg::Bet { bet_id }).unwrap();
+            from_binary::<BetResponse>(&res).unwrap().outcome.unwrap()
+        };
+        let mut expected_bankroll = 100_000_000u128;
+        let mut wins = 0;
+        for secret in 0..40 {
+            let (prediction, dice, payout) = if secret % 2 == 0 {
+                (Prediction::Over(3), None, 1_980_000)
+            } else {
+                (Prediction::Exact(7), Some(Dice { count: 2, sides: 6 }), 5_940_000)
+            };
+            let res = execute(deps.as_mut(), env.clone(), alice.clone(), bet(prediction.clone(), dice, secret)).unwrap();
+            let bet_id = match from_binary(&res.data.unwrap()).unwrap() {
+                ExecuteAnswer::Bet { bet_id } => bet_id,
+                answer => panic!("unexpected answer: {:?}", answer),
+            };
+            assert_eq!(bet_id, secret as u64);
+
+            // the payout is set aside until the bet is settled
+            expected_bankroll += 1_000_000;
+            expected_bankroll -= payout;
+            assert_eq!(bankroll_read(&deps.storage).load().unwrap().u128(), expected_bankroll);
+
+            let res = execute(deps.as_mut(), next_block.clone(), alice.clone(), ExecuteMsg::SettleBet { bet_id }).unwrap();
+            let outcome = outcome(deps.as_ref(), bet_id);
+            assert_eq!(outcome.dice_roll, outcome.dice_faces.iter().map(|face| *face as u16).sum::<u16>());
+            assert_eq!(outcome.won, prediction.is_right(outcome.dice_roll));
+            if outcome.won {
+                wins += 1;
+                assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "alice".to_string(),
+                    amount: coins(payout, "uscrt"),
+                }));
+            } else {
+                expected_bankroll += payout;
+                assert!(res.messages.is_empty());
+            }
+            assert_eq!(bankroll_read(&deps.storage).load().unwrap().u128(), expected_bankroll);
+        }
+        assert!(wins > 0 && wins < 40);
+
+        // the same secret in the same block doesn't give the same roll twice
+        let many_dice = Some(Dice { count: 10, sides: 100 });
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), bet(Prediction::Odd, many_dice.clone(), 1)).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice.clone(), bet(Prediction::Odd, many_dice, 1)).unwrap();
+        let _res = execute(deps.as_mut(), next_block.clone(), alice.clone(), ExecuteMsg::SettleBet { bet_id: 40 }).unwrap();
+        let _res = execute(deps.as_mut(), next_block, alice.clone(), ExecuteMsg::SettleBet { bet_id: 41 }).unwrap();
+        assert_ne!(outcome(deps.as_ref(), 40).dice_faces, outcome(deps.as_ref(), 41).dice_faces);
+
+        // no bets while the contract is stopped
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopJoins };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env, alice, bet(Prediction::Odd, None, 1)).unwrap_err();
+        assert_eq!(err, ContractError::JoinsAreStopped);
+
+        // nor in commit-reveal mode, where the house seed isn't private
+        let (mut deps, env) = commit_reveal_setup();
+        let err = execute(deps.as_mut(), env, mock_info("alice", &coins(1_000_000, "uscrt")), bet(Prediction::Odd, None, 1)).unwrap_err();
+        assert_eq!(err, ContractError::BetsNeedEncryption);
+    }
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    #[test]
+    fn bets_are_settled_in_a_later_block() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+        let info = mock_info("creator", &coins(100_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap();
+
+        // "attacker" is a contract, which could revert its transaction after seeing a losing roll
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::ContractInfo { contract_addr } if contract_addr == "attacker" => SystemResult::Ok(
+                ContractResult::Ok(to_binary(&ContractInfoResponse::new(1, "creator")).unwrap()),
+            ),
+            _ => SystemResult::Err(SystemError::NoSuchContract { addr: String::new() }),
+        });
+        let bet = ExecuteMsg::Bet { prediction: Prediction::Odd, dice: None, secret: Uint128::new(1) };
+        let stake = coins(1_000_000, "uscrt");
+
+        // placing a bet doesn't tell anything about its outcome
+        let res = execute(deps.as_mut(), env.clone(), mock_info("attacker", &stake), bet.clone()).unwrap();
+        assert_eq!(from_binary::<ExecuteAnswer>(&res.data.unwrap()).unwrap(), ExecuteAnswer::Bet { bet_id: 0 });
+        assert_eq!(res.attributes, vec![attr("action", "bet"), attr("bet_id", "0")]);
+        assert!(res.messages.is_empty());
+
+        // nor can it be settled in the same block, or by a contract
+        let settle = ExecuteMsg::SettleBet { bet_id: 0 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), settle.clone()).unwrap_err();
+        assert_eq!(err, ContractError::BetNotSettleableYet);
+        let mut next_block = env.clone();
+        next_block.block.height += 1;
+        let err = execute(deps.as_mut(), next_block.clone(), mock_info("attacker", &[]), settle.clone()).unwrap_err();
+        assert_eq!(err, ContractError::ContractsCannotSettleBets);
+        let msg = ExecuteMsg::SettleBet { bet_id: 1 };
+        let err = execute(deps.as_mut(), next_block.clone(), mock_info("alice", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::BetNotFound);
+
+        // anyone else can settle it, and only the payout, if any, is seen in that block
+        let res = execute(deps.as_mut(), next_block.clone(), mock_info("alice", &[]), settle.clone()).unwrap();
+        assert_eq!(res.data, None);
+        assert_eq!(res.attributes, vec![attr("action", "settle bet"), attr("bet_id", "0")]);
+        let err = execute(deps.as_mut(), next_block.clone(), mock_info("alice", &[]), settle).unwrap_err();
+        assert_eq!(err, ContractError::BetAlreadySettled);
+        let bet_status = |env: Env| -> BetResponse {
+            from_binary(&query(deps.as_ref(), env, QueryMsg::Bet { bet_id: 0 }).unwrap()).unwrap()
+        };
+        assert_eq!(bet_status(next_block.clone()).outcome, None);
+        let mut later_block = next_block.clone();
+        later_block.block.height += 1;
+        let value = bet_status(later_block);
+        assert_eq!(value.stake, Coin::new(1_000_000, "uscrt"));
+        assert_eq!(value.payout, Coin::new(2_000_000, "uscrt"));
+        let outcome = value.outcome.unwrap();
+        assert_eq!(outcome.won, !res.messages.is_empty());
+        assert_eq!(outcome.block_height, next_block.block.height);
+
+        // while everything is stopped, bets waiting to be settled are refunded
+        let res = execute(deps.as_mut(), env.clone(), mock_info("attacker", &stake), bet).unwrap();
+        let bet_id = match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::Bet { bet_id } => bet_id,
+            answer => panic!("unexpected answer: {:?}", answer),
+        };
+        let funds = bankroll_read(&deps.storage).load().unwrap();
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let _res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        let res = execute(deps.as_mut(), next_block, mock_info("alice", &[]), ExecuteMsg::SettleBet { bet_id }).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "attacker".to_string(),
+            amount: stake,
+        }));
+        assert_eq!(bankroll_read(&deps.storage).load().unwrap(), funds + Uint128::new(1_000_000));
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::Bet { bet_id }).is_err());
+    }
 
-        let secret_1 = Uint128::new(1234u128);
-        let msg_player_1 = ExecuteMsg::Join {name: "alice".to_string(), secret: secret_1};
-        let info = mock_info("alice", &coins(1_000_000, uscrt_denom.clone()));
-        let _res = execute(deps.as_mut(), env.clone(), info, msg_player_1).unwrap();
+    #[test]
+    fn high_edge_bets_can_be_refunded() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let msg = InstantiateMsg { house_edge_bps: Some(6_000), ..instantiate_msg() };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let info = mock_info("creator", &coins(10_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap();
+
+        // with a 60% edge, an even-money bet pays less than its stake, which is set aside instead
+        let stake = coins(1_000_000, "uscrt");
+        let bet = ExecuteMsg::Bet { prediction: Prediction::Odd, dice: None, secret: Uint128::new(1) };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("alice", &stake), bet).unwrap();
+        assert_eq!(bets_read(&deps.storage).load(&0u64.to_be_bytes()).unwrap().payout, Uint128::new(800_000));
+        assert_eq!(bankroll_read(&deps.storage).load().unwrap(), Uint128::new(10_000_000));
+
+        // so the stake can still be refunded after the admin took the whole bankroll back
+        let msg = ExecuteMsg::WithdrawBankroll { recipient: "treasurer".to_string(), amount: Uint128::new(10_000_000) };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let msg = ExecuteMsg::SetStatus { status: ContractStatus::StopAll };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let mut next_block = env;
+        next_block.block.height += 1;
+        let res = execute(deps.as_mut(), next_block, mock_info("alice", &[]), ExecuteMsg::SettleBet { bet_id: 0 }).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: stake,
+        }));
+        assert_eq!(bankroll_read(&deps.storage).load().unwrap(), Uint128::zero());
+    }
 
-        let secret_2 = Uint128::new(5678u128);
-        let msg_player_2 = ExecuteMsg::Join {name: "bob".to_string(), secret: secret_2};
-        let info = mock_info("bob", &coins(1_000_000, uscrt_denom.clone()));
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg_player_2).unwrap();
+    #[test]
+    fn bankroll_management() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let bankroll_status = |deps: Deps| -> BankrollResponse {
+            from_binary(&query(deps, mock_env(), QueryMsg::Bankroll {}).unwrap()).unwrap()
+        };
+        let exact_7 = || ExecuteMsg::Bet {
+            prediction: Prediction::Exact(7),
+            dice: Some(Dice { count: 2, sides: 6 }),
+            secret: Uint128::new(1),
+        };
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+
+        // only the admin funds the bankroll, with the stake's coin
+        let info = mock_info("alice", &coins(10_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let info = mock_info("creator", &coins(10_000_000, "utest"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap_err();
+        assert_eq!(err, ContractError::InvalidBankrollDeposit("uscrt".to_string()));
+        let info = mock_info("creator", &coins(10_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap();
+        assert_eq!(bankroll_status(deps.as_ref()), BankrollResponse {
+            balance: Coin::new(10_000_000, "uscrt"),
+            max_exposure_bps: 1_000,
+            max_win: Coin::new(1_000_000, "uscrt"),
+        });
+
+        // a bet on 7 with 2d6 would win 5 times the stake, more than 10% of the bankroll
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), exact_7()).unwrap_err();
+        assert_eq!(err, ContractError::ExposureLimitExceeded(Coin::new(1_000_000, "uscrt")));
+
+        // unless the admin allows it
+        let msg = ExecuteMsg::SetMaxExposure { max_exposure_bps: 5_000 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), alice, exact_7()).unwrap();
+
+        // the admin can take the bankroll back
+        let balance = bankroll_status(deps.as_ref()).balance;
+        let withdraw = |amount: u128| ExecuteMsg::WithdrawBankroll {
+            recipient: "treasurer".to_string(),
+            amount: Uint128::new(amount),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), withdraw(1)).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), withdraw(0)).unwrap_err();
+        assert_eq!(err, ContractError::ZeroWithdrawal);
+        let too_much = balance.amount.u128() + 1;
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), withdraw(too_much)).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientBankroll(balance.clone()));
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), withdraw(balance.amount.u128())).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: "treasurer".to_string(),
+            amount: vec![balance],
+        }));
+        assert_eq!(bankroll_status(deps.as_ref()).balance, Coin::new(0, "uscrt"));
+    }
 
-        // player 2 rolls the dice
-        let _res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::RollDice {}).unwrap();
+    #[test]
+    fn snip20_bankroll_deposits() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            stake: Coin::new(1_000_000, "sscrt"),
+            token: Some(TokenContract {
+                address: Addr::unchecked("sscrt"),
+                code_hash: "sscrt_hash".to_string(),
+            }),
+            ..instantiate_msg()
+        };
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let deposit = |from: &str| ExecuteMsg::Receive {
+            sender: Addr::unchecked(from),
+            from: Addr::unchecked(from),
+            amount: Uint128::new(5_000_000),
+            msg: Some(to_binary(&ReceiveMsg::DepositBankroll {}).unwrap()),
+        };
+
+        // tokens can only be deposited by sending them, and by the admin
+        let info = mock_info("creator", &coins(5_000_000, "uscrt"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::DepositBankroll {}).unwrap_err();
+        assert_eq!(err, ContractError::InvalidBankrollDeposit("sscrt".to_string()));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("sscrt", &[]), deposit("alice")).unwrap_err();
+        assert_eq!(err, ContractError::NotAdmin);
+
+        // the deposit doesn't have to match the stake
+        let _res = execute(deps.as_mut(), env, mock_info("sscrt", &[]), deposit("creator")).unwrap();
+        assert_eq!(bankroll_read(&deps.storage).load().unwrap(), Uint128::new(5_000_000));
+    }
 
-        // advance block height by 1 to be able to query for winner
+    #[test]
+    fn callback_is_notified_of_the_winner() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let _res = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), instantiate_msg()).unwrap();
+
+        let join_callback_msg = |name: &str, contract_addr: &str| ExecuteMsg::Join {
+            game_id: GAME_ID,
+            name: name.to_string(),
+            seats: None,
+            dice: None,
+            secret: Some(Uint128::new(name.len() as u128)),
+            commitment: None,
+            callback: Some(Callback {
+                contract_addr: Addr::unchecked(contract_addr),
+                code_hash: format!("{}_hash", contract_addr),
+                msg: Some(Binary::from(b"round 1")),
+            }),
+        };
+        let alice = mock_info("alice", &coins(1_000_000, "uscrt"));
+        let bob = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), alice, join_callback_msg("alice", "tournament")).unwrap();
+        // only the player who created the game chooses its callback
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), join_callback_msg("bob", "other")).unwrap_err();
+        assert_eq!(err, ContractError::OnlyCreatorChoosesSettings);
+        let _res = execute(deps.as_mut(), env.clone(), bob.clone(), join_msg(GAME_ID, "bob", 3)).unwrap();
+
+        let res = execute(deps.as_mut(), env.clone(), bob, ExecuteMsg::RollDice { game_id: GAME_ID }).unwrap();
         env.block.height += 1;
-        let res = query(deps.as_ref(), env, QueryMsg::WhoWon {}).unwrap();
-        let value: WinnerResponse = from_binary(&res).unwrap();
-        assert_eq!(value.name.is_empty(), false);
+        let winner: WinnerResponse = from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: GAME_ID }).unwrap()).unwrap();
+
+        // the winner is paid first, and the callback can't revert it
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(res.messages[0], SubMsg::new(BankMsg::Send {
+            to_address: winner.name.clone(),
+            amount: coins(2_000_000, "uscrt"),
+        }));
+        let callback = &res.messages[1];
+        assert_eq!(callback.id, CALLBACK_REPLY_ID);
+        assert_eq!(callback.reply_on, ReplyOn::Error);
+        assert_eq!(callback.gas_limit, Some(CALLBACK_GAS_LIMIT));
+        match &callback.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, code_hash, msg, funds }) => {
+                assert_eq!((contract_addr.as_str(), code_hash.as_str()), ("tournament", "tournament_hash"));
+                assert!(funds.is_empty());
+                assert_eq!(msg.len() % BLOCK_SIZE, 0);
+                assert_eq!(from_binary::<CallbackMsg>(msg).unwrap(), CallbackMsg::GameFinished {
+                    game_id: GAME_ID,
+                    winner: Addr::unchecked(winner.name),
+                    dice_roll: winner.dice_roll,
+                    msg: Some(Binary::from(b"round 1")),
+                });
+            },
+            msg => panic!("unexpected message: {:?}", msg),
+        }
 
-        let msg = QueryRequest::Bank(
-            Balance {
-                address: value.addr.to_string(),
-                denom: uscrt_denom.clone(),
-            });
-        let _value: BalanceResponse = from_binary(&deps.querier.handle_query(&msg).unwrap().unwrap()).unwrap();
-        let _expected_amount = Coin { amount: Uint128::new(2_000_000), denom: uscrt_denom };
+        // a failed callback leaves the game finished
+        let reply_msg = |id| Reply { id, result: SubMsgResult::Err("out of gas".to_string()) };
+        let res = reply(deps.as_mut(), env.clone(), reply_msg(CALLBACK_REPLY_ID)).unwrap();
+        assert!(res.messages.is_empty());
+        let status: GameStatusResponse = from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GameStatus { game_id: GAME_ID }).unwrap()).unwrap();
+        assert_eq!(status.state, ContractState::Done);
+        assert!(reply(deps.as_mut(), env, reply_msg(7)).is_err());
+    }
 
-        // TODO: verify winner's balance has increased by 2 SCRT
-        // assert_eq!(value.amount, Coin { amount: Uint128::new(2_000_000), denom: uscrt_denom });
-        // TODO: winner is always "alice" with a dice roll of 1 -- need to troubleshoot that
+    /// Storage as the original single-game contract left it
+    fn legacy_deps(state: &str, block_height: Option<u64>) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        deps.storage.set(&to_length_prefixed(b"config"), state.as_bytes());
+        if let Some(height) = block_height {
+            deps.storage.set(&to_length_prefixed(b"block_height"), height.to_string().as_bytes());
+        }
+        deps
+    }
+
+    fn migrate_msg(admin: Option<&str>) -> MigrateMsg {
+        MigrateMsg { admin: admin.map(String::from), prng_seed: Some(Binary::from(b"entropy")) }
     }
 
     #[test]
-    fn game_is_already_over() {
-        let mut deps = mock_dependencies();
+    fn migrate_a_game_waiting_for_its_second_player() {
+        let mut deps = legacy_deps(
+            r#"{"state":"Got1","player_1":{"name":"alice","addr":"alice","secret":"1234"},"player_2":null,"dice_roll":null,"winner":null}"#,
+            None,
+        );
         let env = mock_env();
 
-        let msg = InstantiateMsg {};
-        let info = mock_info("creator", &coins(1000, "earth"));
-        let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        // the original contract had no admin to take over
+        let err = migrate(deps.as_mut(), env.clone(), migrate_msg(None)).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Migrating the single-game contract needs an admin."));
+
+        let res = migrate(deps.as_mut(), env.clone(), migrate_msg(Some("admin"))).unwrap();
+        assert_eq!(res.attributes[1].value, CONTRACT_VERSION);
+        assert_eq!(contract_version_read(&deps.storage).load().unwrap(), ContractVersion {
+            contract: CONTRACT_NAME.to_string(),
+            version: CONTRACT_VERSION.to_string(),
+        });
+        assert_eq!(config_read(&deps.storage).load().unwrap().admin, Addr::unchecked("admin"));
+
+        // alice still waits for an opponent, with her deposit accounted for
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::GameStatus { game_id: LEGACY_GAME_ID }).unwrap();
+        let status: GameStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(status.state, ContractState::Open);
+        assert_eq!(status.seats, 2);
+        assert_eq!(status.players[0].name, "alice");
+        assert_eq!(player_games_read(&deps.storage).load(b"alice").unwrap(), vec![LEGACY_GAME_ID]);
+
+        // bob joins and the game is played as before
+        let info = mock_info("bob", &coins(1_000_000, "uscrt"));
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), join_msg(LEGACY_GAME_ID, "bob", 5678)).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::RollDice { game_id: LEGACY_GAME_ID }).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. }) => assert_eq!(amount, &coins(2_000_000, "uscrt")),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
 
-        // Player 1 joins the game
-        let secret = Uint128::new(1234u128);
-        let msg = ExecuteMsg::Join {name: "alice".to_string(), secret: secret};
-        let player_1_info = mock_info("alice", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), player_1_info.clone(), msg).unwrap();
+        // migrating a versioned contract leaves its state alone
+        let _res = migrate(deps.as_mut(), env, migrate_msg(None)).unwrap();
+        assert_eq!(config_read(&deps.storage).load().unwrap().admin, Addr::unchecked("admin"));
+    }
 
-        // Player 2 joins the game
-        let secret = Uint128::new(5678u128);
-        let msg = ExecuteMsg::Join {name: "bob".to_string(), secret: secret};
-        let player_2_info = mock_info("bob", &coins(1_000_000, "uscrt"));
-        let _res = execute(deps.as_mut(), env.clone(), player_2_info.clone(), msg).unwrap();
+    #[test]
+    fn migrate_a_full_game() {
+        let mut deps = legacy_deps(
+            r#"{"state":"Got2","player_1":{"name":"alice","addr":"alice","secret":"1234"},"player_2":{"name":"bob","addr":"bob","secret":"5678"},"dice_roll":null,"winner":null}"#,
+            None,
+        );
+        let env = mock_env();
+        let _res = migrate(deps.as_mut(), env.clone(), migrate_msg(Some("admin"))).unwrap();
+
+        // the secrets sent to the original contract decide the roll
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::RollDice { game_id: LEGACY_GAME_ID }).unwrap();
+        let (dice_faces, winner) = pick_winner(&env, &Dice::default(), &[
+            &DiceRoller::new("alice".to_string(), Addr::unchecked("alice"), Uint128::new(1234)),
+            &DiceRoller::new("bob".to_string(), Addr::unchecked("bob"), Uint128::new(5678)),
+        ]);
+        assert_eq!(res.attributes[2].value, dice_faces[0].to_string());
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: winner.addr().to_string(),
+            amount: coins(2_000_000, "uscrt"),
+        }));
+    }
 
-        // player 1 rolls the dice
-        let msg = ExecuteMsg::RollDice {};
-        let _res = execute(deps.as_mut(), env.clone(), player_1_info, msg.clone()).unwrap();
+    #[test]
+    fn migrate_a_finished_game() {
+        let mut deps = legacy_deps(
+            r#"{"state":"Done","player_1":{"name":"alice","addr":"alice","secret":"1234"},"player_2":{"name":"bob","addr":"bob","secret":"5678"},"dice_roll":5,"winner":{"name":"bob","addr":"bob"}}"#,
+            Some(12_345),
+        );
+        let mut env = mock_env();
+        let _res = migrate(deps.as_mut(), env.clone(), migrate_msg(Some("admin"))).unwrap();
 
-        // player 2 tries to roll the dice when game is over
-        let err = execute(deps.as_mut(), env, player_2_info, msg).unwrap_err();
-        match err {
-            ContractError::GameIsAlreadyOver {} => assert!(true),
-            e => {
-                assert!(false);
-                panic!("error: {}", e);
-            },
+        // the winner can still be queried, once the block of the roll is over
+        let err = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: LEGACY_GAME_ID }).unwrap_err();
+        assert_eq!(err, StdError::generic_err(
+            "Querying who won is not allowed until after the winner has been finalized."
+        ));
+        env.block.height += 1;
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::WhoWon { game_id: LEGACY_GAME_ID }).unwrap();
+        assert_eq!(from_binary::<WinnerResponse>(&res).unwrap(), WinnerResponse {
+            name: "bob".to_string(),
+            dice_faces: vec![5],
+            dice_roll: Some(5),
+        });
+
+        // the game is in the history, at the block it ended in
+        let res = query(deps.as_ref(), env, QueryMsg::GameHistory { start_after: None, limit: None }).unwrap();
+        let history: GameHistoryResponse = from_binary(&res).unwrap();
+        assert_eq!(history.total, 1);
+        assert_eq!(history.games[0].game_id, LEGACY_GAME_ID);
+        assert_eq!(history.games[0].players, vec!["alice".to_string(), "bob".to_string()]);
+        assert_eq!(history.games[0].winner, "bob");
+        assert_eq!(history.games[0].dice_roll, Some(5));
+        assert_eq!(history.games[0].stake, Coin::new(1_000_000, "uscrt"));
+        assert_eq!(history.games[0].block_height, 12_345);
+
+        // nothing is left of the old layout, and no deposits are held
+        assert!(deps.storage.get(&to_length_prefixed(b"block_height")).is_none());
+        assert!(player_games_read(&deps.storage).may_load(b"bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn migrate_a_contract_without_a_game() {
+        let mut deps = legacy_deps(
+            r#"{"state":"Init","player_1":null,"player_2":null,"dice_roll":null,"winner":null}"#,
+            Some(12_345),
+        );
+        let _res = migrate(deps.as_mut(), mock_env(), migrate_msg(Some("admin"))).unwrap();
+
+        // no game is carried over, and the height of the last roll is gone with the old layout
+        assert!(games_read(&deps.storage).may_load(&game_key(LEGACY_GAME_ID)).unwrap().is_none());
+        assert!(deps.storage.get(&to_length_prefixed(b"block_height")).is_none());
+        assert_eq!(config_read(&deps.storage).load().unwrap().admin, Addr::unchecked("admin"));
+    }
+
+    #[test]
+    fn migrate_only_this_contract() {
+        let mut deps = mock_dependencies();
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg(Some("admin"))).unwrap_err();
+        assert_eq!(err, StdError::generic_err("No single-game state to migrate."));
+
+        contract_version(&mut deps.storage).save(&ContractVersion {
+            contract: "another-contract".to_string(),
+            version: "1.0.0".to_string(),
+        }).unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg(Some("admin"))).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Can't migrate from another-contract."));
+
+        // nor back to an older version of it
+        let version = |version: &str| ContractVersion {
+            contract: CONTRACT_NAME.to_string(),
+            version: version.to_string(),
+        };
+        for newer in ["0.1.1", "0.2.0", "1.0.0", "0.10.0"] {
+            contract_version(&mut deps.storage).save(&version(newer)).unwrap();
+            let err = migrate(deps.as_mut(), mock_env(), migrate_msg(None)).unwrap_err();
+            assert_eq!(err, StdError::generic_err(format!("Can't migrate back from version {}.", newer)));
+            assert_eq!(contract_version_read(&deps.storage).load().unwrap(), version(newer));
         }
+        contract_version(&mut deps.storage).save(&version("0.0.9")).unwrap();
+        let _res = migrate(deps.as_mut(), mock_env(), migrate_msg(None)).unwrap();
+        assert_eq!(contract_version_read(&deps.storage).load().unwrap(), version(CONTRACT_VERSION));
     }
 }
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Coin, StdError};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -25,8 +25,11 @@ pub enum ContractError {
     #[error("Need to roll the dice to get a winner.")]
     NeedToDiceRollDiceForWinner,
 
-    #[error("Must deposit 1 SCRT to play.")]
-    MustDepositScrtToPlay,
+    #[error("Must deposit {0} to play.")]
+    MustDepositStakeToPlay(Coin),
+
+    #[error("This token is not accepted.")]
+    UnsupportedToken,
 
     #[error("Player 1 not found.")]
     PlayerOneNotFound,
@@ -34,6 +37,90 @@ pub enum ContractError {
     #[error("The game is in progress.")]
     GameIsInProgress,
 
+    #[error("A secret is required to join this game.")]
+    MissingSecret,
+
+    #[error("A commitment, and not the secret itself, is required to join this game.")]
+    MissingCommitment,
+
+    #[error("This game does not use commit-reveal.")]
+    NotACommitRevealGame,
+
+    #[error("The secret has already been revealed.")]
+    AlreadyRevealed,
+
+    #[error("The secret does not match the commitment.")]
+    CommitmentMismatch,
+
+    #[error("The game is still waiting for players to reveal their secrets.")]
+    StillWaitingForReveals,
+
+    #[error("The deadline has not passed yet.")]
+    DeadlineNotReached,
+
+    #[error("There is nothing to claim.")]
+    NothingToClaim,
+
+    #[error("Cancellation was already requested.")]
+    CancellationAlreadyRequested,
+
     #[error("No winner yet.")]
     NoWinnerYet,
+
+    #[error("Only the admin can do this.")]
+    NotAdmin,
+
+    #[error("Joining games is currently disabled.")]
+    JoinsAreStopped,
+
+    #[error("The contract is stopped.")]
+    ContractIsStopped,
+
+    #[error("Deposits can only be refunded this way once the contract is stopped.")]
+    ContractIsNotStopped,
+
+    #[error("Only {0} of fees can be withdrawn.")]
+    InsufficientFees(Coin),
+
+    #[error("Only a positive amount can be withdrawn.")]
+    ZeroWithdrawal,
+
+    #[error("A game must have between 2 and {0} seats.")]
+    InvalidSeats(u8),
+
+    #[error("You already joined this game.")]
+    AlreadyJoined,
+
+    #[error("A game is played with 1 to {count} dice of 2 to {sides} sides.")]
+    InvalidDice { count: u8, sides: u8 },
+
+    #[error("Only the player who creates a game chooses its seats, dice and callback.")]
+    OnlyCreatorChoosesSettings,
+
+    #[error("This prediction can't be lost or can't be won.")]
+    InvalidPrediction,
+
+    #[error("A bet can win at most {0} at the moment.")]
+    ExposureLimitExceeded(Coin),
+
+    #[error("Only {0} of the bankroll can be withdrawn.")]
+    InsufficientBankroll(Coin),
+
+    #[error("Only a positive amount of {0} can be deposited to the bankroll.")]
+    InvalidBankrollDeposit(String),
+
+    #[error("Betting against the house is not available in commit-reveal mode.")]
+    BetsNeedEncryption,
+
+    #[error("Bet not found.")]
+    BetNotFound,
+
+    #[error("The bet is already settled.")]
+    BetAlreadySettled,
+
+    #[error("A bet can only be settled in a later block than the one it was placed in.")]
+    BetNotSettleableYet,
+
+    #[error("Bets can't be settled by contracts.")]
+    ContractsCannotSettleBets,
 }
@@ -0,0 +1,184 @@
+//! Builds the messages and queries other contracts and off-chain tools send to a dice contract.
+//! Contracts using it should depend on this crate with the `library` feature, so that its entry
+//! points aren't exported along with theirs
+
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Coin, CosmosMsg, CustomQuery, Empty, QuerierWrapper, QueryRequest,
+    StdResult, Uint128, WasmMsg, WasmQuery,
+};
+use schemars::JsonSchema;
+use secret_toolkit::utils::space_pad;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::contract::BLOCK_SIZE;
+use crate::msg::{
+    BetResponse, ExecuteMsg, GameStatusResponse, MyBalanceResponse, Prediction, QueryMsg, WinnerResponse,
+};
+use crate::state::Dice;
+
+/// A deployed dice contract, and the stake it is played with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DiceContract {
+    pub address: Addr,
+    pub code_hash: String,
+    /// Deposited with every join and bet. Only native coins can be sent along with a message, so
+    /// tables playing for a SNIP-20 token have to be joined with a token `Send` instead
+    pub stake: Coin,
+}
+
+impl DiceContract {
+    pub fn new(address: Addr, code_hash: String, stake: Coin) -> DiceContract {
+        DiceContract {
+            address,
+            code_hash,
+            stake,
+        }
+    }
+
+    /// Wraps `msg` for this contract, padded like the contract pads its own messages so that its
+    /// size doesn't give away what it is
+    pub fn execute(&self, msg: &ExecuteMsg, funds: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr: self.address.to_string(),
+            code_hash: self.code_hash.clone(),
+            msg: padded(msg)?,
+            funds,
+        }.into())
+    }
+
+    /// Joins a game of the default size with a single d6, or creates it, depositing the stake
+    pub fn join(&self, game_id: u64, name: String, secret: Uint128) -> StdResult<CosmosMsg> {
+        let msg = ExecuteMsg::Join {
+            game_id,
+            name,
+            seats: None,
+            dice: None,
+            secret: Some(secret),
+            commitment: None,
+            callback: None,
+        };
+        self.execute(&msg, vec![self.stake.clone()])
+    }
+
+    pub fn roll_dice(&self, game_id: u64) -> StdResult<CosmosMsg> {
+        self.execute(&ExecuteMsg::RollDice { game_id }, vec![])
+    }
+
+    pub fn leave(&self, game_id: u64) -> StdResult<CosmosMsg> {
+        self.execute(&ExecuteMsg::Leave { game_id }, vec![])
+    }
+
+    /// Bets the stake against the house. The bet has to be settled by an account in a later block,
+    /// and its id is in the data of the answer
+    pub fn bet(&self, prediction: Prediction, dice: Option<Dice>, secret: Uint128) -> StdResult<CosmosMsg> {
+        let msg = ExecuteMsg::Bet { prediction, dice, secret };
+        self.execute(&msg, vec![self.stake.clone()])
+    }
+
+    /// Wraps `msg` for this contract, padded like execute messages
+    pub fn query_request<C: CustomQuery>(&self, msg: &QueryMsg) -> StdResult<QueryRequest<C>> {
+        Ok(QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: self.address.to_string(),
+            code_hash: self.code_hash.clone(),
+            msg: padded(msg)?,
+        }))
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, querier: &QuerierWrapper<Empty>, msg: &QueryMsg) -> StdResult<T> {
+        querier.query(&self.query_request(msg)?)
+    }
+
+    pub fn who_won(&self, querier: &QuerierWrapper<Empty>, game_id: u64) -> StdResult<WinnerResponse> {
+        self.query(querier, &QueryMsg::WhoWon { game_id })
+    }
+
+    pub fn game_status(&self, querier: &QuerierWrapper<Empty>, game_id: u64) -> StdResult<GameStatusResponse> {
+        self.query(querier, &QueryMsg::GameStatus { game_id })
+    }
+
+    /// A bet, and its outcome from the block after it was settled
+    pub fn bet_status(&self, querier: &QuerierWrapper<Empty>, bet_id: u64) -> StdResult<BetResponse> {
+        self.query(querier, &QueryMsg::Bet { bet_id })
+    }
+
+    /// The deposits `address` has in unfinished games, authenticated with their viewing key
+    pub fn my_balance(
+        &self,
+        querier: &QuerierWrapper<Empty>,
+        address: String,
+        key: String,
+    ) -> StdResult<MyBalanceResponse> {
+        self.query(querier, &QueryMsg::MyBalance { address, key })
+    }
+}
+
+pub(crate) fn padded<T: Serialize>(msg: &T) -> StdResult<Binary> {
+    let mut msg = to_binary(msg)?;
+    space_pad(&mut msg.0, BLOCK_SIZE);
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{from_binary, from_slice, ContractResult, SystemResult};
+    use cosmwasm_std::testing::MockQuerier;
+
+    fn dice_contract() -> DiceContract {
+        DiceContract::new(Addr::unchecked("dice"), "dice_hash".to_string(), Coin::new(1_000_000, "uscrt"))
+    }
+
+    #[test]
+    fn messages_carry_the_code_hash_and_the_stake() {
+        let msg = dice_contract().join(7, "alice".to_string(), Uint128::new(1234)).unwrap();
+        let (msg, funds) = match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, code_hash, msg, funds }) => {
+                assert_eq!(contract_addr, "dice");
+                assert_eq!(code_hash, "dice_hash");
+                (msg, funds)
+            },
+            msg => panic!("unexpected message: {:?}", msg),
+        };
+        assert_eq!(funds, vec![Coin::new(1_000_000, "uscrt")]);
+
+        // the padding is trailing whitespace, which the contract's JSON parser ignores
+        assert_eq!(msg.len() % BLOCK_SIZE, 0);
+        assert_eq!(from_binary::<ExecuteMsg>(&msg).unwrap(), ExecuteMsg::Join {
+            game_id: 7,
+            name: "alice".to_string(),
+            seats: None,
+            dice: None,
+            secret: Some(Uint128::new(1234)),
+            commitment: None,
+            callback: None,
+        });
+
+        match dice_contract().roll_dice(7).unwrap() {
+            CosmosMsg::Wasm(WasmMsg::Execute { funds, .. }) => assert!(funds.is_empty()),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn queries_are_answered_by_the_contract() {
+        let mut querier: MockQuerier = MockQuerier::new(&[]);
+        querier.update_wasm(|request| match request {
+            WasmQuery::Smart { contract_addr, code_hash, msg } => {
+                assert_eq!((contract_addr.as_str(), code_hash.as_str()), ("dice", "dice_hash"));
+                assert_eq!(from_slice::<QueryMsg>(msg).unwrap(), QueryMsg::WhoWon { game_id: 7 });
+                let winner = WinnerResponse {
+                    name: "bob".to_string(),
+                    dice_faces: vec![5],
+                    dice_roll: Some(5),
+                };
+                SystemResult::Ok(ContractResult::Ok(to_binary(&winner).unwrap()))
+            },
+            _ => panic!("unexpected query: {:?}", request),
+        });
+
+        let winner = dice_contract().who_won(&QuerierWrapper::new(&querier), 7).unwrap();
+        assert_eq!(winner.name, "bob");
+        assert_eq!(winner.dice_roll, Some(5));
+    }
+}
@@ -0,0 +1,53 @@
+//! Storage layout of the original single-game contract, read only to migrate it
+
+use cosmwasm_std::{Addr, Storage, Uint128};
+use cosmwasm_storage::{ReadonlySingleton, singleton, Singleton, singleton_read};
+
+use serde::{Deserialize, Serialize};
+
+/// The single game was stored under the key that now holds the `Config`
+const CONFIG_KEY: &[u8] = b"config";
+/// Height of the block where the dice were rolled
+const BLOCK_HEIGHT_KEY: &[u8] = b"block_height";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyState {
+    pub state: LegacyContractState,
+    pub player_1: Option<LegacyDiceRoller>,
+    pub player_2: Option<LegacyDiceRoller>,
+    pub dice_roll: Option<u8>,
+    pub winner: Option<LegacyWinner>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum LegacyContractState {
+    Init,
+    Got1,
+    Got2,
+    Done
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyDiceRoller {
+    pub name: String,
+    pub addr: Addr,
+    pub secret: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyWinner {
+    pub name: String,
+    pub addr: Addr,
+}
+
+pub fn legacy_state_read(storage: &dyn Storage) -> ReadonlySingleton<'_, LegacyState> {
+    singleton_read(storage, CONFIG_KEY)
+}
+
+pub fn legacy_block_height(storage: &mut dyn Storage) -> Singleton<'_, u64> {
+    singleton(storage, BLOCK_HEIGHT_KEY)
+}
+
+pub fn legacy_block_height_read(storage: &dyn Storage) -> ReadonlySingleton<'_, u64> {
+    singleton_read(storage, BLOCK_HEIGHT_KEY)
+}
@@ -1,4 +1,7 @@
 pub mod contract;
 mod error;
+pub mod helpers;
+mod legacy;
 pub mod msg;
+pub mod random;
 pub mod state;
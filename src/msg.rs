@@ -1,27 +1,352 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Binary, Coin, Timestamp, Uint128};
+use secret_toolkit::permit::Permit;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct InstantiateMsg {}
+use crate::state::{
+    BetOutcome, Callback, CommitRevealConfig, ContractState, ContractStatus, Dice, Expiration, GameRecord, Timeout,
+    TokenContract,
+};
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    /// The amount each player deposits to join a game. The winner receives twice this amount
+    pub stake: Coin,
+    /// Play with a SNIP-20 token instead of a native coin. The stake denom must then be the
+    /// token contract address
+    pub token: Option<TokenContract>,
+    /// Enable commit-reveal joins, for chains without encrypted inputs
+    pub commit_reveal: Option<CommitRevealConfig>,
+    /// After this long without an opponent, player 1 can get their deposit back with `ClaimTimeout`
+    pub join_timeout: Option<Timeout>,
+    /// After this long without a dice roll, both players can be refunded with `ClaimTimeout`
+    pub roll_timeout: Option<Timeout>,
+    /// Entropy for generating viewing keys and the house seed. Required to take bets, unless the
+    /// chain provides its own randomness and the contract is built with the `random` feature
+    pub prng_seed: Option<Binary>,
+    /// The admin of the contract. Defaults to its creator
+    pub admin: Option<String>,
+    /// House fee in basis points of the pot, i.e. 100 is 1%, below 10000. Defaults to no fee
+    pub fee_bps: Option<u16>,
+    /// House edge on bets against the house, in basis points of the fair payout, below 10000.
+    /// Defaults to none
+    pub house_edge_bps: Option<u16>,
+    /// The most a single bet can win, in basis points of the bankroll. Defaults to 1000, i.e. 10%
+    pub max_exposure_bps: Option<u16>,
+}
+
+/// Only the original single-game contract, which had no settings, needs these
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// The admin of the migrated contract. Required when migrating the single-game contract
+    pub admin: Option<String>,
+    /// Entropy for generating viewing keys and the house seed. Required like in `InstantiateMsg`
+    /// when migrating the single-game contract
+    pub prng_seed: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    Join { name: String, secret: Uint128 },
-    RollDice {},
-    Leave {},
+    /// Join with a `secret`, or with a `commitment` to it in commit-reveal mode. The player who
+    /// creates the game chooses its number of `seats`, 2 by default, and its `dice`, a single d6
+    /// by default, and can have a contract notified of its winner with a `callback`. The other
+    /// players must leave these out
+    Join {
+        game_id: u64,
+        name: String,
+        seats: Option<u8>,
+        dice: Option<Dice>,
+        secret: Option<Uint128>,
+        commitment: Option<Binary>,
+        callback: Option<Callback>,
+    },
+    /// Bet the stake against the house that the total of the `dice`, a single d6 by default, will
+    /// match the `prediction`. Answers with the id of the bet, to be settled in a later block
+    Bet {
+        prediction: Prediction,
+        dice: Option<Dice>,
+        secret: Uint128,
+    },
+    /// Roll the dice of a bet placed in an earlier block, and pay the player if they won. Anyone
+    /// can settle a bet, except contracts. The outcome is only known through the `Bet` query, from
+    /// the next block on
+    SettleBet { bet_id: u64 },
+    /// Disclose the secret behind a commitment once the game is full
+    Reveal { game_id: u64, secret: Uint128, salt: Binary },
+    RollDice { game_id: u64 },
+    Leave { game_id: u64 },
+    /// Ask to cancel a full game before the dice are rolled. The game is cancelled, and both
+    /// players refunded, once the other player asks too. If they never do, `ClaimTimeout` refunds
+    /// both players once the roll deadline has passed
+    Cancel { game_id: u64 },
+    /// Settle a game whose deadline passed: refund the deposits, or award the pot to the player
+    /// who revealed their secret in a commit-reveal game
+    ClaimTimeout { game_id: u64 },
+    /// Generate a viewing key for the sender, used to query their private data
+    CreateViewingKey { entropy: String },
+    /// Set the sender's viewing key to a key of their choice
+    SetViewingKey { key: String },
+    /// Invalidate the sender's permits named `permit_name`
+    RevokePermit { permit_name: String },
+    /// Admin only: hand the admin role over to `address`
+    ChangeAdmin { address: String },
+    /// Admin only: stop joins, or stop all games
+    SetStatus { status: ContractStatus },
+    /// Admin only, once the contract is stopped: refund the deposits of up to `limit` unfinished
+    /// games, or of all of them. Can be repeated until no deposits are left
+    EmergencyRefund { limit: Option<u32> },
+    /// Admin only: send `amount` of the accrued house fees to `recipient`
+    WithdrawFees { recipient: String, amount: Uint128 },
+    /// Admin only: add the coins sent along to the bankroll that pays the winning bets. With a
+    /// SNIP-20 stake, send the tokens with `ReceiveMsg::DepositBankroll` instead
+    DepositBankroll {},
+    /// Admin only: send `amount` of the bankroll to `recipient`
+    WithdrawBankroll { recipient: String, amount: Uint128 },
+    /// Admin only: change the most a single bet can win, in basis points of the bankroll
+    SetMaxExposure { max_exposure_bps: u16 },
+    /// SNIP-20 receiver interface, called by the token contract when tokens are sent to us
+    Receive {
+        sender: Addr,
+        from: Addr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+}
+
+/// Data returned by execute messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteAnswer {
+    CreateViewingKey { key: String },
+    Bet { bet_id: u64 },
+}
+
+/// What a player bets the total of the dice will be
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Prediction {
+    /// Strictly more than the threshold
+    Over(u16),
+    /// Strictly less than the threshold
+    Under(u16),
+    Odd,
+    Even,
+    Exact(u16),
+}
+
+impl Prediction {
+    pub fn is_right(&self, total: u16) -> bool {
+        match self {
+            Prediction::Over(threshold) => total > *threshold,
+            Prediction::Under(threshold) => total < *threshold,
+            Prediction::Odd => total % 2 == 1,
+            Prediction::Even => total % 2 == 0,
+            Prediction::Exact(expected) => total == *expected,
+        }
+    }
+}
+
+/// The message sent to the callback contract of a game once it has a winner. The game is settled
+/// whether or not the callback succeeds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackMsg {
+    GameFinished {
+        game_id: u64,
+        winner: Addr,
+        /// `None` when the game was won because the other players never revealed their secret
+        dice_roll: Option<u16>,
+        /// The `msg` of the callback
+        msg: Option<Binary>,
+    },
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+/// The message embedded in a SNIP-20 `Send`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Join {
+        game_id: u64,
+        name: String,
+        seats: Option<u8>,
+        dice: Option<Dice>,
+        secret: Option<Uint128>,
+        commitment: Option<Binary>,
+        callback: Option<Callback>,
+    },
+    Bet {
+        prediction: Prediction,
+        dice: Option<Dice>,
+        secret: Uint128,
+    },
+    /// Admin only: add the tokens to the bankroll
+    DepositBankroll {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    WhoWon {},
+    WhoWon { game_id: u64 },
+    /// The public state of a game. Never includes the players' secrets
+    GameStatus { game_id: u64 },
+    /// Finished games, oldest first, without the players' addresses. `start_after` is the `index`
+    /// of the last record of the previous page
+    GameHistory { start_after: Option<u64>, limit: Option<u32> },
+    /// The finished games of `address`, oldest first, authenticated with their viewing key
+    MyGames {
+        address: String,
+        key: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The deposits `address` has in unfinished games, authenticated with their viewing key
+    MyBalance { address: String, key: String },
+    /// The admin and the status of the contract
+    ContractStatus {},
+    /// The house fee and the fees accrued so far
+    Fees {},
+    /// The bankroll that pays the winning bets, and how much a single bet can win
+    Bankroll {},
+    /// A bet against the house, and its outcome once it is settled
+    Bet { bet_id: u64 },
+    /// A private query authenticated with a permit signed by the querier instead of a viewing key.
+    /// The permit must list this contract in its `allowed_tokens`
+    WithPermit { permit: Permit, query: QueryWithPermit },
+}
+
+/// The private queries available with a permit. `MyGames` needs the `history` permission and
+/// `MyBalance` the `balance` permission
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    MyGames { start_after: Option<u64>, limit: Option<u32> },
+    MyBalance {},
 }
 
 /// We define a custom struct for each query response
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct WinnerResponse {
+    /// The name the winner joined with. Their address is only shown to them, in `MyGames`
+    pub name: String,
+    /// The face each die landed on
+    pub dice_faces: Vec<u8>,
+    /// The total of the dice. `None` when the game was won because the other players never
+    /// revealed their secret
+    pub dice_roll: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GameStatusResponse {
+    pub state: ContractState,
+    /// Number of players the game is played with
+    pub seats: u8,
+    pub dice: Dice,
+    /// The players who joined, in seat order
+    pub players: Vec<PlayerResponse>,
+    pub stake: Coin,
+    /// Deadline for the game to fill up, or for the dice to be rolled once it is full
+    pub deadline: Option<Expiration>,
+    /// Deadline for revealing secrets, in commit-reveal mode
+    pub reveal_deadline: Option<Expiration>,
+    /// The players who asked to cancel the game
+    pub cancel_requested_by: Vec<Addr>,
+    /// Whether `WhoWon` can be queried, i.e. the game is over and the block it ended in is final
+    pub winner_queryable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PlayerResponse {
     pub name: String,
     pub addr: Addr,
-    pub dice_roll: u8,
+    /// Whether the player revealed their secret. Always true outside of commit-reveal mode
+    pub revealed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GameHistoryResponse {
+    pub games: Vec<PublicGameRecord>,
+    /// Total number of records
+    pub total: u64,
+}
+
+/// A finished game as listed in the public history, where players are only known by their names
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PublicGameRecord {
+    pub index: u64,
+    pub game_id: u64,
+    pub players: Vec<String>,
+    pub stake: Coin,
+    pub dice: Dice,
+    pub dice_faces: Vec<u8>,
+    pub dice_roll: Option<u16>,
+    pub winner: String,
+    pub block_height: u64,
+    pub timestamp: Timestamp,
+}
+
+impl From<GameRecord> for PublicGameRecord {
+    fn from(record: GameRecord) -> Self {
+        PublicGameRecord {
+            index: record.index,
+            game_id: record.game_id,
+            players: record.players.into_iter().map(|player| player.name).collect(),
+            stake: record.stake,
+            dice: record.dice,
+            dice_faces: record.dice_faces,
+            dice_roll: record.dice_roll,
+            winner: record.winner.name,
+            block_height: record.block_height,
+            timestamp: record.timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MyGamesResponse {
+    pub games: Vec<GameRecord>,
+    /// Total number of records of the player
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractStatusResponse {
+    pub admin: Addr,
+    pub status: ContractStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeesResponse {
+    pub fee_bps: u16,
+    /// Fees kept from the pots and not withdrawn yet
+    pub accrued: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BankrollResponse {
+    /// Leaves out what is set aside to pay the bets waiting to be settled
+    pub balance: Coin,
+    pub max_exposure_bps: u16,
+    /// The most a single bet can win on top of its stake
+    pub max_win: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BetResponse {
+    pub prediction: Prediction,
+    pub dice: Dice,
+    pub stake: Coin,
+    /// What the bet pays if the prediction is right
+    pub payout: Coin,
+    /// Like the winner of a game, only shown once the block the bet was settled in is over
+    pub outcome: Option<BetOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MyBalanceResponse {
+    /// Total deposits held in unfinished games. They are paid back if a game is left, cancelled or
+    /// times out
+    pub pending: Coin,
+    /// The unfinished games holding a deposit, once per deposit
+    pub game_ids: Vec<u64>,
 }
@@ -0,0 +1,183 @@
+use cosmwasm_std::{Binary, Timestamp, Uint128};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Derives the seed of the dice roll from the players' secrets, in seat order, and the block time.
+///
+/// The block time is public, so on chains that provide it we also mix in the protocol's
+/// `env.block.random`. When it is `None` the seed is the same as it has always been.
+pub fn derive_seed(
+    secrets: &[Uint128],
+    block_time: &Timestamp,
+    block_random: Option<&Binary>,
+) -> [u8; 32] {
+    let mut combined_secret: Vec<u8> = vec![];
+    for secret in secrets {
+        combined_secret.extend(&secret.to_be_bytes());
+    }
+    combined_secret.extend(block_time.to_string().as_bytes());
+
+    if let Some(block_random) = block_random {
+        combined_secret.extend(block_random.as_slice());
+    }
+
+    Sha256::digest(&combined_secret).into()
+}
+
+/// Derives the seed of a bet against the house. The player's secret is mixed with the house's
+/// private seed, which the player doesn't know, so they can't pick a secret that wins
+pub fn derive_bet_seed(
+    house_seed: &[u8],
+    secret: &Uint128,
+    block_time: &Timestamp,
+    block_random: Option<&Binary>,
+) -> [u8; 32] {
+    let mut combined_secret: Vec<u8> = house_seed.to_vec();
+    combined_secret.extend(derive_seed(&[*secret], block_time, block_random));
+
+    Sha256::digest(&combined_secret).into()
+}
+
+/// The commitment a player submits when joining a commit-reveal game: sha256(secret || salt),
+/// where the secret is encoded as 16 big-endian bytes
+pub fn commitment(secret: &Uint128, salt: &Binary) -> [u8; 32] {
+    let mut preimage: Vec<u8> = secret.to_be_bytes().to_vec();
+    preimage.extend(salt.as_slice());
+
+    Sha256::digest(&preimage).into()
+}
+
+/// Rolls a die with `sides` faces, numbered from 1, which must be at least 1.
+///
+/// 2^32 is not a multiple of most die sizes, so taking a draw modulo `sides` would favor the low
+/// faces. The draws past the last whole multiple of `sides` are rejected and drawn again instead.
+pub fn roll_die<R: RngCore>(rng: &mut R, sides: u8) -> u8 {
+    let sides = sides as u64;
+    let draws = 1u64 << 32;
+    let accepted = draws - draws % sides;
+
+    loop {
+        let draw = rng.next_u32() as u64;
+        if draw < accepted {
+            return (draw % sides + 1) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    fn secrets() -> (Uint128, Uint128, Timestamp) {
+        (Uint128::new(1234), Uint128::new(5678), Timestamp::from_nanos(1_571_797_419_879_305_533))
+    }
+
+    #[test]
+    fn seed_is_deterministic() {
+        let (secret_1, secret_2, time) = secrets();
+        let random = Binary::from(vec![7u8; 32]);
+
+        assert_eq!(
+            derive_seed(&[secret_1, secret_2], &time, None),
+            derive_seed(&[secret_1, secret_2], &time, None),
+        );
+        assert_eq!(
+            derive_seed(&[secret_1, secret_2], &time, Some(&random)),
+            derive_seed(&[secret_1, secret_2], &time, Some(&random)),
+        );
+    }
+
+    #[test]
+    fn seed_without_block_random_is_unchanged() {
+        let (secret_1, secret_2, time) = secrets();
+
+        let mut combined_secret: Vec<u8> = secret_1.to_be_bytes().to_vec();
+        combined_secret.extend(&secret_2.to_be_bytes());
+        combined_secret.extend(time.to_string().as_bytes());
+        let expected: [u8; 32] = Sha256::digest(&combined_secret).into();
+
+        assert_eq!(derive_seed(&[secret_1, secret_2], &time, None), expected);
+    }
+
+    #[test]
+    fn block_random_changes_the_seed() {
+        let (secret_1, secret_2, time) = secrets();
+        let random_1 = Binary::from(vec![1u8; 32]);
+        let random_2 = Binary::from(vec![2u8; 32]);
+
+        let seed = derive_seed(&[secret_1, secret_2], &time, None);
+        let seed_1 = derive_seed(&[secret_1, secret_2], &time, Some(&random_1));
+        let seed_2 = derive_seed(&[secret_1, secret_2], &time, Some(&random_2));
+        assert_ne!(seed, seed_1);
+        assert_ne!(seed_1, seed_2);
+    }
+
+    #[test]
+    fn every_input_changes_the_seed() {
+        let (secret_1, secret_2, time) = secrets();
+        let seed = derive_seed(&[secret_1, secret_2], &time, None);
+
+        assert_ne!(seed, derive_seed(&[Uint128::new(1), secret_2], &time, None));
+        assert_ne!(seed, derive_seed(&[secret_1, Uint128::new(1)], &time, None));
+        assert_ne!(seed, derive_seed(&[secret_1, secret_2], &time.plus_seconds(6), None));
+        // the order of the players matters
+        assert_ne!(seed, derive_seed(&[secret_2, secret_1], &time, None));
+    }
+
+    #[test]
+    fn commitment_binds_secret_and_salt() {
+        let secret = Uint128::new(1234);
+        let salt = Binary::from(b"pepper".to_vec());
+
+        let mut preimage = secret.to_be_bytes().to_vec();
+        preimage.extend(b"pepper");
+        let expected: [u8; 32] = Sha256::digest(&preimage).into();
+
+        assert_eq!(commitment(&secret, &salt), expected);
+        assert_ne!(commitment(&Uint128::new(1235), &salt), expected);
+        assert_ne!(commitment(&secret, &Binary::from(b"salt".to_vec())), expected);
+    }
+
+    #[test]
+    fn draws_past_the_last_multiple_are_rejected() {
+        // 2^32 = 715_827_882 * 6 + 4, so the 4 highest draws would make 1 to 4 more likely
+        let mut rng = StepRng::new(u32::MAX as u64 - 3, 1);
+        assert_eq!(roll_die(&mut rng, 6), 1);
+        assert_eq!(rng.next_u32(), 1);
+
+        let mut rng = StepRng::new(u32::MAX as u64 - 4, 1);
+        assert_eq!(roll_die(&mut rng, 6), 6);
+
+        // powers of two divide 2^32, so every draw is used
+        let mut rng = StepRng::new(u32::MAX as u64, 1);
+        assert_eq!(roll_die(&mut rng, 8), 8);
+        assert_eq!(roll_die(&mut rng, 1), 1);
+    }
+
+    #[test]
+    fn rolls_are_uniform() {
+        const ROLLS: u32 = 100_000;
+
+        for (seed, sides) in [(1u8, 2u8), (2, 6), (3, 10), (4, 20), (5, 100)] {
+            let mut rng = ChaChaRng::from_seed([seed; 32]);
+            let mut counts = vec![0u32; sides as usize];
+            for _ in 0..ROLLS {
+                let face = roll_die(&mut rng, sides);
+                assert!((1..=sides).contains(&face));
+                counts[face as usize - 1] += 1;
+            }
+
+            // chi-squared statistic, compared with the 99.9th percentile for `sides - 1` degrees
+            // of freedom, approximated from above by df + 4 * sqrt(2 * df) + 10
+            let expected = ROLLS as f64 / sides as f64;
+            let chi_squared: f64 = counts.iter()
+                .map(|count| (*count as f64 - expected).powi(2) / expected)
+                .sum();
+            let df = (sides - 1) as f64;
+            assert!(chi_squared < df + 4.0 * (2.0 * df).sqrt() + 10.0, "d{}: {}", sides, chi_squared);
+        }
+    }
+}
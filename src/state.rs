@@ -1,55 +1,232 @@
-use cosmwasm_std::{Addr, Uint128, Storage};
+use cosmwasm_std::{
+    Addr, Binary, BlockInfo, Coin, OverflowError, OverflowOperation, StdResult, Timestamp, Uint128,
+    Storage,
+};
 use cosmwasm_storage::{
-    ReadonlySingleton, singleton, Singleton,
-    singleton_read,
+    bucket, bucket_read, Bucket, ReadonlyBucket,
+    ReadonlySingleton, singleton, Singleton, singleton_read,
 };
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::msg::Prediction;
+
 const CONFIG_KEY: &[u8] = b"config";
+const GAMES_KEY: &[u8] = b"games";
 const BLOCK_HEIGHT_KEY: &[u8] = b"block_height";
+const HISTORY_KEY: &[u8] = b"history";
+const HISTORY_COUNT_KEY: &[u8] = b"history_count";
+const PLAYER_HISTORY_KEY: &[u8] = b"player_history";
+const PLAYER_HISTORY_COUNT_KEY: &[u8] = b"player_history_count";
+const PLAYER_GAMES_KEY: &[u8] = b"player_games";
+const OPEN_GAMES_KEY: &[u8] = b"open_games";
+const OPEN_GAME_POSITIONS_KEY: &[u8] = b"open_game_positions";
+const OPEN_GAME_COUNT_KEY: &[u8] = b"open_game_count";
+const TREASURY_KEY: &[u8] = b"treasury";
+const BANKROLL_KEY: &[u8] = b"bankroll";
+const HOUSE_SEED_KEY: &[u8] = b"house_seed";
+const BETS_KEY: &[u8] = b"bets";
+const BET_COUNT_KEY: &[u8] = b"bet_count";
+const CONTRACT_VERSION_KEY: &[u8] = b"contract_version";
+/// storage prefix of the permits revoked with `RevokePermit`
+pub const PREFIX_REVOKED_PERMITS: &str = "revoked_permits";
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// The contract and version the storage was last written by, checked on migration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContractVersion {
+    pub contract: String,
+    pub version: String,
+}
+
+/// Contract-wide settings, set once at instantiation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// The amount every player has to deposit to join a game
+    pub stake: Coin,
+    /// The SNIP-20 token used for deposits and payouts, if the stake isn't a native coin
+    pub token: Option<TokenContract>,
+    /// Players join with a commitment and reveal their secret later, for chains where
+    /// inputs are not encrypted
+    pub commit_reveal: Option<CommitRevealConfig>,
+    /// How long player 1 waits for an opponent before they can claim a refund
+    pub join_timeout: Option<Timeout>,
+    /// How long a full game waits for the dice to be rolled before both players can be refunded
+    pub roll_timeout: Option<Timeout>,
+    /// Can change the admin and the contract status, and refund all deposits once it is stopped
+    pub admin: Addr,
+    pub status: ContractStatus,
+    /// House fee, in basis points of the pot, kept when the dice are rolled
+    pub fee_bps: u16,
+    /// House edge on bets against the house, in basis points of the fair payout
+    pub house_edge_bps: u16,
+    /// The most a single bet can win, in basis points of the bankroll
+    pub max_exposure_bps: u16,
+}
+
+/// Kill switch, set by the admin
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    #[default]
+    Normal,
+    /// No new players can join, but games in progress can still be played
+    StopJoins,
+    /// No one can join or roll the dice. Deposits can be refunded with `EmergencyRefund`, and
+    /// `ClaimTimeout` refunds every player instead of awarding the pot to those who revealed.
+    /// Settling a bet refunds its stake
+    StopAll,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CommitRevealConfig {
+    /// Number of blocks, counted from when the game is full, that players have to reveal
+    pub reveal_timeout: u64,
+}
+
+/// How long a game waits at a given stage, counted from when it reached that stage
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Timeout {
+    Blocks(u64),
+    Seconds(u64),
+}
+
+impl Timeout {
+    /// The deadline of a timeout that starts at `block`, or an error if it is too far away to be
+    /// represented
+    pub fn after(&self, block: &BlockInfo) -> StdResult<Expiration> {
+        match self {
+            Timeout::Blocks(blocks) => block.height
+                .checked_add(*blocks)
+                .map(Expiration::AtHeight)
+                .ok_or_else(|| OverflowError::new(OverflowOperation::Add, block.height, blocks).into()),
+            Timeout::Seconds(seconds) => seconds
+                .checked_mul(1_000_000_000)
+                .and_then(|nanos| block.time.nanos().checked_add(nanos))
+                .map(|nanos| Expiration::AtTime(Timestamp::from_nanos(nanos)))
+                .ok_or_else(|| OverflowError::new(OverflowOperation::Add, block.time, seconds).into()),
+        }
+    }
+}
+
+/// The last block height or time at which a game can still make progress
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    AtHeight(u64),
+    AtTime(Timestamp),
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiration::AtHeight(height) => block.height > *height,
+            Expiration::AtTime(time) => block.time > *time,
+        }
+    }
+}
+
+/// A SNIP-20 token contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenContract {
+    pub address: Addr,
+    pub code_hash: String,
+}
+
+/// A contract notified with `CallbackMsg::GameFinished` when the game it was set on has a winner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Callback {
+    pub contract_addr: Addr,
+    pub code_hash: String,
+    /// Passed back as is in the notification
+    pub msg: Option<Binary>,
+}
+
+/// The state of a single game. Every game lives in its own record, keyed by its game id
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct State {
     pub state: ContractState,
-    pub player_1: Option<DiceRoller>,
-    pub player_2: Option<DiceRoller>,
-    pub dice_roll: Option<u8>,
+    /// Number of players the game needs, chosen by the player who creates it
+    pub seats: u8,
+    /// The players who joined, in seat order
+    pub players: Vec<DiceRoller>,
+    /// The dice the game is played with, chosen by the player who creates it
+    pub dice: Dice,
+    /// The face each die landed on
+    pub dice_faces: Vec<u8>,
+    /// The total of the dice, which decides the winner
+    pub dice_roll: Option<u16>,
     pub winner: Option<Winner>,
+    /// Deadline for revealing secrets, in commit-reveal mode
+    pub reveal_deadline: Option<Expiration>,
+    /// Deadline for the game to fill up while `Open`, or for the dice to be rolled once `Full`
+    pub deadline: Option<Expiration>,
+    /// The players who asked to cancel the game, waiting for all the others to agree
+    pub cancel_requested_by: Vec<Addr>,
+    /// Set by the player who creates the game
+    pub callback: Option<Callback>,
 }
 
-impl State {
-    pub fn default() -> State {
-        State {
-            state: ContractState::default(),
-            player_1: None,
-            player_2: None,
-            dice_roll: None,
-            winner: None,
+/// `count` dice with `sides` faces each, e.g. 2d6
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dice {
+    pub count: u8,
+    pub sides: u8,
+}
+
+impl Default for Dice {
+    fn default() -> Dice {
+        Dice { count: 1, sides: 6 }
+    }
+}
+
+impl Dice {
+    /// The lowest possible total
+    pub fn min_total(&self) -> u16 {
+        self.count as u16
+    }
+
+    /// The highest possible total
+    pub fn max_total(&self) -> u16 {
+        self.count as u16 * self.sides as u16
+    }
+
+    /// The most seats a game played with these dice can have. Every total of a single die is as
+    /// likely, so its faces can be shared among up to `sides` players. Totals of several dice are
+    /// not, and can only be split fairly into low and high totals between 2 players
+    pub fn max_seats(&self) -> u8 {
+        if self.count == 1 {
+            self.sides
+        } else {
+            2
         }
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+impl State {
+    pub fn is_player(&self, addr: &Addr) -> bool {
+        self.players.iter().any(|player| player.addr() == addr)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default, JsonSchema)]
 pub enum ContractState {
+    #[default]
     Init,
-    Got1,
-    Got2,
+    /// Waiting for players to take the free seats
+    Open,
+    /// All the seats are taken, the dice can be rolled
+    Full,
     Done
 }
 
-impl Default for ContractState {
-    fn default() -> Self {
-        Self::Init
-    }
-}
-
 impl From<u8> for ContractState {
     fn from(num: u8) -> Self {
         match num {
             0 => ContractState::Init,
-            1 => ContractState::Got1,
-            2 => ContractState::Got2,
+            1 => ContractState::Open,
+            2 => ContractState::Full,
             3 => ContractState::Done,
             _ => ContractState::Init
         }
@@ -60,8 +237,8 @@ impl From<ContractState> for u8 {
     fn from(state: ContractState) -> Self {
         match state {
             ContractState::Init => 0,
-            ContractState::Got1 => 1,
-            ContractState::Got2 => 2,
+            ContractState::Open => 1,
+            ContractState::Full => 2,
             ContractState::Done => 3,
         }
     }
@@ -71,7 +248,9 @@ impl From<ContractState> for u8 {
 pub struct DiceRoller {
     name: String,
     addr: Addr,
-    secret: Uint128
+    secret: Uint128,
+    /// sha256(secret || salt), kept until the secret is revealed
+    commitment: Option<Binary>,
 }
 
 impl Default for DiceRoller {
@@ -79,7 +258,8 @@ impl Default for DiceRoller {
         DiceRoller {
             name: String::from(""),
             addr: Addr::unchecked(""),
-            secret: Uint128::from(0u32)
+            secret: Uint128::from(0u32),
+            commitment: None,
         }
     }
 }
@@ -91,7 +271,18 @@ impl DiceRoller {
         DiceRoller {
             name,
             addr,
-            secret
+            secret,
+            commitment: None,
+        }
+    }
+
+    /// Constructor function for commit-reveal games. The secret stays unknown until `reveal`
+    pub fn new_committed(name: String, addr: Addr, commitment: Binary) -> DiceRoller {
+        DiceRoller {
+            name,
+            addr,
+            secret: Uint128::zero(),
+            commitment: Some(commitment),
         }
     }
 
@@ -109,6 +300,20 @@ impl DiceRoller {
     pub fn secret(&self) -> &Uint128 {
         &self.secret
     }
+
+    pub fn commitment(&self) -> Option<&Binary> {
+        self.commitment.as_ref()
+    }
+
+    pub fn has_revealed(&self) -> bool {
+        self.commitment.is_none()
+    }
+
+    /// Stores the revealed secret. The caller is responsible for checking it against the commitment
+    pub fn reveal(&mut self, secret: Uint128) {
+        self.secret = secret;
+        self.commitment = None;
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -139,18 +344,260 @@ impl Winner {
     }
 }
 
-pub fn config(storage: &mut dyn Storage) -> Singleton<State> {
+pub fn config(storage: &mut dyn Storage) -> Singleton<'_, Config> {
     singleton(storage, CONFIG_KEY)
 }
 
-pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<State> {
+pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Config> {
     singleton_read(storage, CONFIG_KEY)
 }
 
-pub fn block_height(storage: &mut dyn Storage) -> Singleton<u64> {
-    singleton(storage, BLOCK_HEIGHT_KEY)
+/// A finished game, as archived in the game history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GameRecord {
+    /// Position of the record in the history
+    pub index: u64,
+    pub game_id: u64,
+    pub players: Vec<PlayerRecord>,
+    pub stake: Coin,
+    pub dice: Dice,
+    pub dice_faces: Vec<u8>,
+    /// The total of the dice. `None` when the game was won by forfeit
+    pub dice_roll: Option<u16>,
+    pub winner: PlayerRecord,
+    pub block_height: u64,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PlayerRecord {
+    pub name: String,
+    pub addr: Addr,
+}
+
+/// A bet against the house. It is placed in one block and settled in a later one, so that whoever
+/// places it can't see how it turns out before the stake is paid
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Bet {
+    pub player: Addr,
+    pub prediction: Prediction,
+    pub dice: Dice,
+    pub secret: Uint128,
+    pub stake: Uint128,
+    /// What a right prediction pays
+    pub payout: Uint128,
+    /// Height of the block the bet was placed in
+    pub block_height: u64,
+    pub outcome: Option<BetOutcome>,
+}
+
+impl Bet {
+    /// What is set aside from the bankroll until the bet is settled. It covers the payout, and the
+    /// stake too in case the bet is refunded, as a high house edge can make the payout the smaller
+    pub fn reserved(&self) -> Uint128 {
+        self.payout.max(self.stake)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BetOutcome {
+    pub dice_faces: Vec<u8>,
+    /// The total of the dice
+    pub dice_roll: u16,
+    pub won: bool,
+    /// Height of the block the bet was settled in
+    pub block_height: u64,
+}
+
+pub fn contract_version(storage: &mut dyn Storage) -> Singleton<'_, ContractVersion> {
+    singleton(storage, CONTRACT_VERSION_KEY)
+}
+
+pub fn contract_version_read(storage: &dyn Storage) -> ReadonlySingleton<'_, ContractVersion> {
+    singleton_read(storage, CONTRACT_VERSION_KEY)
+}
+
+/// Storage key of a game record
+pub fn game_key(game_id: u64) -> [u8; 8] {
+    game_id.to_be_bytes()
 }
 
-pub fn block_height_read(storage: &dyn Storage) -> ReadonlySingleton<u64> {
-    singleton_read(storage, BLOCK_HEIGHT_KEY)
+pub fn games(storage: &mut dyn Storage) -> Bucket<'_, State> {
+    bucket(storage, GAMES_KEY)
 }
+
+pub fn games_read(storage: &dyn Storage) -> ReadonlyBucket<'_, State> {
+    bucket_read(storage, GAMES_KEY)
+}
+
+pub fn block_height(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, BLOCK_HEIGHT_KEY)
+}
+
+pub fn block_height_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, BLOCK_HEIGHT_KEY)
+}
+
+/// Ids of the unfinished games each player has a deposit in, keyed by address
+pub fn player_games(storage: &mut dyn Storage) -> Bucket<'_, Vec<u64>> {
+    bucket(storage, PLAYER_GAMES_KEY)
+}
+
+pub fn player_games_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Vec<u64>> {
+    bucket_read(storage, PLAYER_GAMES_KEY)
+}
+
+/// Ids of the games holding deposits, so that they can all be refunded in an emergency. They are
+/// kept at positions `0..open_game_count`, and the position of each game is kept by its id, so
+/// that adding or removing a game doesn't cost more as more games are open
+pub fn open_games(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, OPEN_GAMES_KEY)
+}
+
+pub fn open_games_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, OPEN_GAMES_KEY)
+}
+
+pub fn open_game_positions(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, OPEN_GAME_POSITIONS_KEY)
+}
+
+pub fn open_game_positions_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, OPEN_GAME_POSITIONS_KEY)
+}
+
+pub fn open_game_count(storage: &mut dyn Storage) -> Singleton<'_, u64> {
+    singleton(storage, OPEN_GAME_COUNT_KEY)
+}
+
+pub fn open_game_count_read(storage: &dyn Storage) -> ReadonlySingleton<'_, u64> {
+    singleton_read(storage, OPEN_GAME_COUNT_KEY)
+}
+
+/// Adds a game to the open games, unless it is there already
+pub fn add_open_game(storage: &mut dyn Storage, game_id: u64) -> StdResult<()> {
+    if open_game_positions_read(storage).may_load(&game_id.to_be_bytes())?.is_some() {
+        return Ok(());
+    }
+
+    let count = open_game_count_read(storage).may_load()?.unwrap_or_default();
+    open_games(storage).save(&count.to_be_bytes(), &game_id)?;
+    open_game_positions(storage).save(&game_id.to_be_bytes(), &count)?;
+    open_game_count(storage).save(&(count + 1))
+}
+
+/// Removes a game from the open games by moving the last one in its place
+pub fn remove_open_game(storage: &mut dyn Storage, game_id: u64) -> StdResult<()> {
+    let position = match open_game_positions_read(storage).may_load(&game_id.to_be_bytes())? {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let last = open_game_count_read(storage).load()? - 1;
+    if position != last {
+        let last_game_id = open_games_read(storage).load(&last.to_be_bytes())?;
+        open_games(storage).save(&position.to_be_bytes(), &last_game_id)?;
+        open_game_positions(storage).save(&last_game_id.to_be_bytes(), &position)?;
+    }
+    open_games(storage).remove(&last.to_be_bytes());
+    open_game_positions(storage).remove(&game_id.to_be_bytes());
+    open_game_count(storage).save(&last)
+}
+
+/// House fees accrued and not withdrawn yet, in the stake's currency
+pub fn treasury(storage: &mut dyn Storage) -> Singleton<'_, Uint128> {
+    singleton(storage, TREASURY_KEY)
+}
+
+pub fn treasury_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Uint128> {
+    singleton_read(storage, TREASURY_KEY)
+}
+
+/// The house's funds, which pay the bets players win against it, in the stake's currency
+pub fn bankroll(storage: &mut dyn Storage) -> Singleton<'_, Uint128> {
+    singleton(storage, BANKROLL_KEY)
+}
+
+pub fn bankroll_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Uint128> {
+    singleton_read(storage, BANKROLL_KEY)
+}
+
+/// Bets against the house, keyed by their id
+pub fn bets(storage: &mut dyn Storage) -> Bucket<'_, Bet> {
+    bucket(storage, BETS_KEY)
+}
+
+pub fn bets_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Bet> {
+    bucket_read(storage, BETS_KEY)
+}
+
+/// Number of bets placed so far, which is also the id of the next one
+pub fn bet_count(storage: &mut dyn Storage) -> Singleton<'_, u64> {
+    singleton(storage, BET_COUNT_KEY)
+}
+
+pub fn bet_count_read(storage: &dyn Storage) -> ReadonlySingleton<'_, u64> {
+    singleton_read(storage, BET_COUNT_KEY)
+}
+
+/// Private entropy mixed into the seed of every bet, and renewed after each one
+pub fn house_seed(storage: &mut dyn Storage) -> Singleton<'_, Vec<u8>> {
+    singleton(storage, HOUSE_SEED_KEY)
+}
+
+pub fn house_seed_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Vec<u8>> {
+    singleton_read(storage, HOUSE_SEED_KEY)
+}
+
+/// The history is append-only. Records are keyed by their index, and the indexes of each player's
+/// games are kept in a per-player list, since we can't iterate over storage
+pub fn history(storage: &mut dyn Storage) -> Bucket<'_, GameRecord> {
+    bucket(storage, HISTORY_KEY)
+}
+
+pub fn history_read(storage: &dyn Storage) -> ReadonlyBucket<'_, GameRecord> {
+    bucket_read(storage, HISTORY_KEY)
+}
+
+pub fn history_count(storage: &mut dyn Storage) -> Singleton<'_, u64> {
+    singleton(storage, HISTORY_COUNT_KEY)
+}
+
+pub fn history_count_read(storage: &dyn Storage) -> ReadonlySingleton<'_, u64> {
+    singleton_read(storage, HISTORY_COUNT_KEY)
+}
+
+pub fn player_history<'a>(storage: &'a mut dyn Storage, addr: &Addr) -> Bucket<'a, u64> {
+    Bucket::multilevel(storage, &[PLAYER_HISTORY_KEY, addr.as_bytes()])
+}
+
+pub fn player_history_read<'a>(storage: &'a dyn Storage, addr: &Addr) -> ReadonlyBucket<'a, u64> {
+    ReadonlyBucket::multilevel(storage, &[PLAYER_HISTORY_KEY, addr.as_bytes()])
+}
+
+pub fn player_history_count(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, PLAYER_HISTORY_COUNT_KEY)
+}
+
+pub fn player_history_count_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, PLAYER_HISTORY_COUNT_KEY)
+}
+
+/// Appends a finished game to the history and to the history of each of its players
+pub fn append_history(storage: &mut dyn Storage, mut record: GameRecord) -> StdResult<u64> {
+    let index = history_count_read(storage).may_load()?.unwrap_or_default();
+    record.index = index;
+
+    history(storage).save(&index.to_be_bytes(), &record)?;
+    history_count(storage).save(&(index + 1))?;
+
+    for player in &record.players {
+        let count = player_history_count_read(storage)
+            .may_load(player.addr.as_bytes())?
+            .unwrap_or_default();
+        player_history(storage, &player.addr).save(&count.to_be_bytes(), &index)?;
+        player_history_count(storage).save(player.addr.as_bytes(), &(count + 1))?;
+    }
+
+    Ok(index)
+}
\ No newline at end of file
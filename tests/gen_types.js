@@ -0,0 +1,117 @@
+// Writes the TypeScript types of the contract's messages and responses to `int_types.ts`, from
+// the JSON schemas `cargo run --example schema` writes to `schema/`. Run it with `make ts-types`
+// rather than editing `int_types.ts` by hand
+
+const fs = require("fs");
+const path = require("path");
+
+const SCHEMA_DIR = path.join(__dirname, "..", "schema");
+const OUT_FILE = path.join(__dirname, "int_types.ts");
+
+const pad = (indent) => "  ".repeat(indent);
+
+function comment(description, indent) {
+  if (!description) {
+    return "";
+  }
+  const lines = description.split("\n").map((line) => `${pad(indent)} *${line ? " " + line : ""}`);
+  return `${pad(indent)}/**\n${lines.join("\n")}\n${pad(indent)} */\n`;
+}
+
+function refName(ref) {
+  return ref.replace("#/definitions/", "");
+}
+
+function union(types) {
+  return [...new Set(types)].join(" | ");
+}
+
+function objectType(schema, indent) {
+  const properties = Object.entries(schema.properties || {});
+  if (properties.length === 0) {
+    return "{}";
+  }
+  const required = schema.required || [];
+  const lines = properties.map(([name, property]) => {
+    const optional = required.includes(name) ? "" : "?";
+    return `${comment(property.description, indent + 1)}${pad(indent + 1)}${name}${optional}: ${tsType(property, indent + 1)},`;
+  });
+  return `{\n${lines.join("\n")}\n${pad(indent)}}`;
+}
+
+function primitive(type, schema, indent) {
+  switch (type) {
+    case "object":
+      return objectType(schema, indent);
+    case "array": {
+      const items = tsType(schema.items, indent);
+      return items.includes(" | ") ? `(${items})[]` : `${items}[]`;
+    }
+    case "integer":
+    case "number":
+      return "number";
+    case "string":
+    case "boolean":
+    case "null":
+      return type;
+    default:
+      throw new Error(`Unsupported schema type: ${JSON.stringify(type)}`);
+  }
+}
+
+function tsType(schema, indent) {
+  if (schema.$ref) {
+    return refName(schema.$ref);
+  }
+  if (schema.allOf) {
+    return schema.allOf.map((part) => tsType(part, indent)).join(" & ");
+  }
+  if (schema.anyOf || schema.oneOf) {
+    return union((schema.anyOf || schema.oneOf).map((part) => tsType(part, indent)));
+  }
+  if (schema.enum) {
+    return union(schema.enum.map((value) => JSON.stringify(value)));
+  }
+  const types = Array.isArray(schema.type) ? schema.type : [schema.type];
+  return union(types.map((type) => primitive(type, schema, indent)));
+}
+
+function declaration(name, schema) {
+  let body;
+  if (schema.oneOf) {
+    // one line per variant of an enum
+    body = schema.oneOf
+      .map((variant) => `\n${comment(variant.description, 1)}  | ${tsType(variant, 1)}`)
+      .join("");
+  } else {
+    body = " " + tsType(schema, 0);
+  }
+  return `${comment(schema.description, 0)}export type ${name} =${body};\n`;
+}
+
+const roots = [];
+const definitions = new Map();
+
+for (const file of fs.readdirSync(SCHEMA_DIR).filter((file) => file.endsWith(".json")).sort()) {
+  const schema = JSON.parse(fs.readFileSync(path.join(SCHEMA_DIR, file), "utf8"));
+  roots.push([schema.title, schema]);
+
+  for (const [name, definition] of Object.entries(schema.definitions || {})) {
+    const known = definitions.get(name);
+    if (known !== undefined && JSON.stringify(known) !== JSON.stringify(definition)) {
+      throw new Error(`${name} is defined differently in ${file}`);
+    }
+    definitions.set(name, definition);
+  }
+}
+
+const declarations = [
+  ...roots.map(([name, schema]) => declaration(name, schema)),
+  ...[...definitions.keys()]
+    .filter((name) => !roots.some(([root]) => root === name))
+    .sort()
+    .map((name) => declaration(name, definitions.get(name))),
+];
+
+const header = "// Generated from schema/ by tests/gen_types.js. Don't edit it, run `make ts-types` instead\n";
+fs.writeFileSync(OUT_FILE, `${header}\n${declarations.join("\n")}`);
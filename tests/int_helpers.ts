@@ -1,6 +1,23 @@
 import { Wallet, SecretNetworkClient, Tx } from "secretjs";
 import { AminoWallet } from "secretjs/dist/wallet_amino";
-import { Account } from "./int_types";
+
+export type jsEnv = {
+  accounts: Account[];
+  contracts: ContractInfo[];
+}
+
+export type Account = {
+  address: string;
+  mnemonic: string;
+  walletAmino: AminoWallet;
+  walletProto: Wallet;
+  secretjs: SecretNetworkClient;
+};
+
+export type ContractInfo = {
+  hash: string;
+  address: string;
+}
 
 
 /////////////////////////////////////////////////////////////////////////////////
@@ -1,59 +1,767 @@
-import { SecretNetworkClient, Wallet, } from "secretjs";
-import { AminoWallet } from "secretjs/dist/wallet_amino";
+// Generated from schema/ by tests/gen_types.js. Don't edit it, run `make ts-types` instead
 
-export type jsEnv = {
-  accounts: Account[];
-  contracts: ContractInfo[];
-}
+export type BankrollResponse = {
+  /**
+   * Leaves out what is set aside to pay the bets waiting to be settled
+   */
+  balance: Coin,
+  max_exposure_bps: number,
+  /**
+   * The most a single bet can win on top of its stake
+   */
+  max_win: Coin,
+};
+
+export type BetResponse = {
+  dice: Dice,
+  /**
+   * Like the winner of a game, only shown once the block the bet was settled in is over
+   */
+  outcome?: BetOutcome | null,
+  /**
+   * What the bet pays if the prediction is right
+   */
+  payout: Coin,
+  prediction: Prediction,
+  stake: Coin,
+};
+
+/**
+ * The message sent to the callback contract of a game once it has a winner. The game is settled whether or not the callback succeeds
+ */
+export type CallbackMsg =
+  | {
+    game_finished: {
+      /**
+       * `None` when the game was won because the other players never revealed their secret
+       */
+      dice_roll?: number | null,
+      game_id: number,
+      /**
+       * The `msg` of the callback
+       */
+      msg?: Binary | null,
+      winner: Addr,
+    },
+  };
+
+export type ContractStatusResponse = {
+  admin: Addr,
+  status: ContractStatus,
+};
+
+/**
+ * Data returned by execute messages
+ */
+export type ExecuteAnswer =
+  | {
+    create_viewing_key: {
+      key: string,
+    },
+  }
+  | {
+    bet: {
+      bet_id: number,
+    },
+  };
+
+export type ExecuteMsg =
+  /**
+   * Join with a `secret`, or with a `commitment` to it in commit-reveal mode. The player who creates the game chooses its number of `seats`, 2 by default, and its `dice`, a single d6 by default, and can have a contract notified of its winner with a `callback`. The other players must leave these out
+   */
+  | {
+    join: {
+      callback?: Callback | null,
+      commitment?: Binary | null,
+      dice?: Dice | null,
+      game_id: number,
+      name: string,
+      seats?: number | null,
+      secret?: Uint128 | null,
+    },
+  }
+  /**
+   * Bet the stake against the house that the total of the `dice`, a single d6 by default, will match the `prediction`. Answers with the id of the bet, to be settled in a later block
+   */
+  | {
+    bet: {
+      dice?: Dice | null,
+      prediction: Prediction,
+      secret: Uint128,
+    },
+  }
+  /**
+   * Roll the dice of a bet placed in an earlier block, and pay the player if they won. Anyone can settle a bet, except contracts. The outcome is only known through the `Bet` query, from the next block on
+   */
+  | {
+    settle_bet: {
+      bet_id: number,
+    },
+  }
+  /**
+   * Disclose the secret behind a commitment once the game is full
+   */
+  | {
+    reveal: {
+      game_id: number,
+      salt: Binary,
+      secret: Uint128,
+    },
+  }
+  | {
+    roll_dice: {
+      game_id: number,
+    },
+  }
+  | {
+    leave: {
+      game_id: number,
+    },
+  }
+  /**
+   * Ask to cancel a full game before the dice are rolled. The game is cancelled, and both players refunded, once the other player asks too. If they never do, `ClaimTimeout` refunds both players once the roll deadline has passed
+   */
+  | {
+    cancel: {
+      game_id: number,
+    },
+  }
+  /**
+   * Settle a game whose deadline passed: refund the deposits, or award the pot to the player who revealed their secret in a commit-reveal game
+   */
+  | {
+    claim_timeout: {
+      game_id: number,
+    },
+  }
+  /**
+   * Generate a viewing key for the sender, used to query their private data
+   */
+  | {
+    create_viewing_key: {
+      entropy: string,
+    },
+  }
+  /**
+   * Set the sender's viewing key to a key of their choice
+   */
+  | {
+    set_viewing_key: {
+      key: string,
+    },
+  }
+  /**
+   * Invalidate the sender's permits named `permit_name`
+   */
+  | {
+    revoke_permit: {
+      permit_name: string,
+    },
+  }
+  /**
+   * Admin only: hand the admin role over to `address`
+   */
+  | {
+    change_admin: {
+      address: string,
+    },
+  }
+  /**
+   * Admin only: stop joins, or stop all games
+   */
+  | {
+    set_status: {
+      status: ContractStatus,
+    },
+  }
+  /**
+   * Admin only, once the contract is stopped: refund the deposits of up to `limit` unfinished games, or of all of them. Can be repeated until no deposits are left
+   */
+  | {
+    emergency_refund: {
+      limit?: number | null,
+    },
+  }
+  /**
+   * Admin only: send `amount` of the accrued house fees to `recipient`
+   */
+  | {
+    withdraw_fees: {
+      amount: Uint128,
+      recipient: string,
+    },
+  }
+  /**
+   * Admin only: add the coins sent along to the bankroll that pays the winning bets. With a SNIP-20 stake, send the tokens with `ReceiveMsg::DepositBankroll` instead
+   */
+  | {
+    deposit_bankroll: {},
+  }
+  /**
+   * Admin only: send `amount` of the bankroll to `recipient`
+   */
+  | {
+    withdraw_bankroll: {
+      amount: Uint128,
+      recipient: string,
+    },
+  }
+  /**
+   * Admin only: change the most a single bet can win, in basis points of the bankroll
+   */
+  | {
+    set_max_exposure: {
+      max_exposure_bps: number,
+    },
+  }
+  /**
+   * SNIP-20 receiver interface, called by the token contract when tokens are sent to us
+   */
+  | {
+    receive: {
+      amount: Uint128,
+      from: Addr,
+      msg?: Binary | null,
+      sender: Addr,
+    },
+  };
+
+export type FeesResponse = {
+  /**
+   * Fees kept from the pots and not withdrawn yet
+   */
+  accrued: Coin,
+  fee_bps: number,
+};
+
+export type GameHistoryResponse = {
+  games: PublicGameRecord[],
+  /**
+   * Total number of records
+   */
+  total: number,
+};
+
+export type GameStatusResponse = {
+  /**
+   * The players who asked to cancel the game
+   */
+  cancel_requested_by: Addr[],
+  /**
+   * Deadline for the game to fill up, or for the dice to be rolled once it is full
+   */
+  deadline?: Expiration | null,
+  dice: Dice,
+  /**
+   * The players who joined, in seat order
+   */
+  players: PlayerResponse[],
+  /**
+   * Deadline for revealing secrets, in commit-reveal mode
+   */
+  reveal_deadline?: Expiration | null,
+  /**
+   * Number of players the game is played with
+   */
+  seats: number,
+  stake: Coin,
+  state: ContractState,
+  /**
+   * Whether `WhoWon` can be queried, i.e. the game is over and the block it ended in is final
+   */
+  winner_queryable: boolean,
+};
+
+export type InstantiateMsg = {
+  /**
+   * The admin of the contract. Defaults to its creator
+   */
+  admin?: string | null,
+  /**
+   * Enable commit-reveal joins, for chains without encrypted inputs
+   */
+  commit_reveal?: CommitRevealConfig | null,
+  /**
+   * House fee in basis points of the pot, i.e. 100 is 1%, below 10000. Defaults to no fee
+   */
+  fee_bps?: number | null,
+  /**
+   * House edge on bets against the house, in basis points of the fair payout, below 10000. Defaults to none
+   */
+  house_edge_bps?: number | null,
+  /**
+   * After this long without an opponent, player 1 can get their deposit back with `ClaimTimeout`
+   */
+  join_timeout?: Timeout | null,
+  /**
+   * The most a single bet can win, in basis points of the bankroll. Defaults to 1000, i.e. 10%
+   */
+  max_exposure_bps?: number | null,
+  /**
+   * Entropy for generating viewing keys and the house seed. Required to take bets, unless the chain provides its own randomness and the contract is built with the `random` feature
+   */
+  prng_seed?: Binary | null,
+  /**
+   * After this long without a dice roll, both players can be refunded with `ClaimTimeout`
+   */
+  roll_timeout?: Timeout | null,
+  /**
+   * The amount each player deposits to join a game. The winner receives twice this amount
+   */
+  stake: Coin,
+  /**
+   * Play with a SNIP-20 token instead of a native coin. The stake denom must then be the token contract address
+   */
+  token?: TokenContract | null,
+};
+
+/**
+ * Only the original single-game contract, which had no settings, needs these
+ */
+export type MigrateMsg = {
+  /**
+   * The admin of the migrated contract. Required when migrating the single-game contract
+   */
+  admin?: string | null,
+  /**
+   * Entropy for generating viewing keys and the house seed. Required like in `InstantiateMsg` when migrating the single-game contract
+   */
+  prng_seed?: Binary | null,
+};
+
+export type MyBalanceResponse = {
+  /**
+   * The unfinished games holding a deposit, once per deposit
+   */
+  game_ids: number[],
+  /**
+   * Total deposits held in unfinished games. They are paid back if a game is left, cancelled or times out
+   */
+  pending: Coin,
+};
+
+export type MyGamesResponse = {
+  games: GameRecord[],
+  /**
+   * Total number of records of the player
+   */
+  total: number,
+};
+
+export type QueryMsg =
+  | {
+    who_won: {
+      game_id: number,
+    },
+  }
+  /**
+   * The public state of a game. Never includes the players' secrets
+   */
+  | {
+    game_status: {
+      game_id: number,
+    },
+  }
+  /**
+   * Finished games, oldest first, without the players' addresses. `start_after` is the `index` of the last record of the previous page
+   */
+  | {
+    game_history: {
+      limit?: number | null,
+      start_after?: number | null,
+    },
+  }
+  /**
+   * The finished games of `address`, oldest first, authenticated with their viewing key
+   */
+  | {
+    my_games: {
+      address: string,
+      key: string,
+      limit?: number | null,
+      start_after?: number | null,
+    },
+  }
+  /**
+   * The deposits `address` has in unfinished games, authenticated with their viewing key
+   */
+  | {
+    my_balance: {
+      address: string,
+      key: string,
+    },
+  }
+  /**
+   * The admin and the status of the contract
+   */
+  | {
+    contract_status: {},
+  }
+  /**
+   * The house fee and the fees accrued so far
+   */
+  | {
+    fees: {},
+  }
+  /**
+   * The bankroll that pays the winning bets, and how much a single bet can win
+   */
+  | {
+    bankroll: {},
+  }
+  /**
+   * A bet against the house, and its outcome once it is settled
+   */
+  | {
+    bet: {
+      bet_id: number,
+    },
+  }
+  /**
+   * A private query authenticated with a permit signed by the querier instead of a viewing key. The permit must list this contract in its `allowed_tokens`
+   */
+  | {
+    with_permit: {
+      permit: Permit_for_TokenPermissions,
+      query: QueryWithPermit,
+    },
+  };
 
-export type Account = {
-  address: string;
-  mnemonic: string;
-  walletAmino: AminoWallet;
-  walletProto: Wallet;
-  secretjs: SecretNetworkClient;
+/**
+ * The message embedded in a SNIP-20 `Send`
+ */
+export type ReceiveMsg =
+  | {
+    join: {
+      callback?: Callback | null,
+      commitment?: Binary | null,
+      dice?: Dice | null,
+      game_id: number,
+      name: string,
+      seats?: number | null,
+      secret?: Uint128 | null,
+    },
+  }
+  | {
+    bet: {
+      dice?: Dice | null,
+      prediction: Prediction,
+      secret: Uint128,
+    },
+  }
+  /**
+   * Admin only: add the tokens to the bankroll
+   */
+  | {
+    deposit_bankroll: {},
+  };
+
+/**
+ * We define a custom struct for each query response
+ */
+export type WinnerResponse = {
+  /**
+   * The face each die landed on
+   */
+  dice_faces: number[],
+  /**
+   * The total of the dice. `None` when the game was won because the other players never revealed their secret
+   */
+  dice_roll?: number | null,
+  /**
+   * The name the winner joined with. Their address is only shown to them, in `MyGames`
+   */
+  name: string,
+};
+
+/**
+ * A human readable address.
+ *
+ * In Cosmos, this is typically bech32 encoded. But for multi-chain smart contracts no assumptions should be made other than being UTF-8 encoded and of reasonable length.
+ *
+ * This type represents a validated address. It can be created in the following ways 1. Use `Addr::unchecked(input)` 2. Use `let checked: Addr = deps.api.addr_validate(input)?` 3. Use `let checked: Addr = deps.api.addr_humanize(canonical_addr)?` 4. Deserialize from JSON. This must only be done from JSON that was validated before such as a contract's state. `Addr` must not be used in messages sent by the user because this would result in unvalidated instances.
+ *
+ * This type is immutable. If you really need to mutate it (Really? Are you sure?), create a mutable copy using `let mut mutable = Addr::to_string()` and operate on that `String` instance.
+ */
+export type Addr = string;
+
+export type BetOutcome = {
+  /**
+   * Height of the block the bet was settled in
+   */
+  block_height: number,
+  dice_faces: number[],
+  /**
+   * The total of the dice
+   */
+  dice_roll: number,
+  won: boolean,
+};
+
+/**
+ * Binary is a wrapper around Vec<u8> to add base64 de/serialization with serde. It also adds some helper methods to help encode inline.
+ *
+ * This is only needed as serde-json-{core,wasm} has a horrible encoding for Vec<u8>. See also <https://github.com/CosmWasm/cosmwasm/blob/main/docs/MESSAGE_TYPES.md>.
+ */
+export type Binary = string;
+
+/**
+ * A contract notified with `CallbackMsg::GameFinished` when the game it was set on has a winner
+ */
+export type Callback = {
+  code_hash: string,
+  contract_addr: Addr,
+  /**
+   * Passed back as is in the notification
+   */
+  msg?: Binary | null,
 };
 
-export type ContractInfo = {
-  hash: string;
-  address: string;
-}
+export type Coin = {
+  amount: Uint128,
+  denom: string,
+};
 
-export type Balance = {
-  address: string,
-  amount: string,
+export type CommitRevealConfig = {
+  /**
+   * Number of blocks, counted from when the game is full, that players have to reveal
+   */
+  reveal_timeout: number,
 };
 
-export type InitMsg = {  }
+export type ContractState =
+  | "Init" | "Done"
+  /**
+   * Waiting for players to take the free seats
+   */
+  | "Open"
+  /**
+   * All the seats are taken, the dice can be rolled
+   */
+  | "Full";
+
+/**
+ * Kill switch, set by the admin
+ */
+export type ContractStatus =
+  | "normal"
+  /**
+   * No new players can join, but games in progress can still be played
+   */
+  | "stop_joins"
+  /**
+   * No one can join or roll the dice. Deposits can be refunded with `EmergencyRefund`, and `ClaimTimeout` refunds every player instead of awarding the pot to those who revealed. Settling a bet refunds its stake
+   */
+  | "stop_all";
+
+/**
+ * `count` dice with `sides` faces each, e.g. 2d6
+ */
+export type Dice = {
+  count: number,
+  sides: number,
+};
 
-export type JoinMsg = {
-  join: {
-    name: string,
-    secret: string,
+/**
+ * The last block height or time at which a game can still make progress
+ */
+export type Expiration =
+  | {
+    at_height: number,
   }
-}
+  | {
+    at_time: Timestamp,
+  };
 
-export type RollDiceMsg = {
-  roll_dice: {  }
-}
+/**
+ * A finished game, as archived in the game history
+ */
+export type GameRecord = {
+  block_height: number,
+  dice: Dice,
+  dice_faces: number[],
+  /**
+   * The total of the dice. `None` when the game was won by forfeit
+   */
+  dice_roll?: number | null,
+  game_id: number,
+  /**
+   * Position of the record in the history
+   */
+  index: number,
+  players: PlayerRecord[],
+  stake: Coin,
+  timestamp: Timestamp,
+  winner: PlayerRecord,
+};
 
-export type LeaveMsg = {
-  leave: {  }
-}
+export type PermitParams_for_TokenPermissions = {
+  allowed_tokens: string[],
+  chain_id: string,
+  permissions: TokenPermissions[],
+  permit_name: string,
+};
 
-export type HandleMsg = JoinMsg | RollDiceMsg | LeaveMsg;
+export type PermitSignature = {
+  pub_key: PubKey,
+  signature: Binary,
+};
 
+export type Permit_for_TokenPermissions = {
+  params: PermitParams_for_TokenPermissions,
+  signature: PermitSignature,
+};
 
-export type WhoWonMsg = {
-  who_won: {  }
-}
+export type PlayerRecord = {
+  addr: Addr,
+  name: string,
+};
 
-export type QueryMsg = WhoWonMsg;
+export type PlayerResponse = {
+  addr: Addr,
+  name: string,
+  /**
+   * Whether the player revealed their secret. Always true outside of commit-reveal mode
+   */
+  revealed: boolean,
+};
 
-export type WhoWonResponse = { 
-    name: string,
-    addr: string,
-    dice_roll: number,
+/**
+ * What a player bets the total of the dice will be
+ */
+export type Prediction =
+  | "odd" | "even"
+  /**
+   * Strictly more than the threshold
+   */
+  | {
+    over: number,
+  }
+  /**
+   * Strictly less than the threshold
+   */
+  | {
+    under: number,
+  }
+  | {
+    exact: number,
+  };
+
+export type PubKey = {
+  /**
+   * ignored, but must be "tendermint/PubKeySecp256k1" otherwise the verification will fail
+   */
+  type: string,
+  /**
+   * Secp256k1 PubKey
+   */
+  value: Binary,
 };
 
-export type QueryResponse = WhoWonResponse
\ No newline at end of file
+/**
+ * A finished game as listed in the public history, where players are only known by their names
+ */
+export type PublicGameRecord = {
+  block_height: number,
+  dice: Dice,
+  dice_faces: number[],
+  dice_roll?: number | null,
+  game_id: number,
+  index: number,
+  players: string[],
+  stake: Coin,
+  timestamp: Timestamp,
+  winner: string,
+};
+
+/**
+ * The private queries available with a permit. `MyGames` needs the `history` permission and `MyBalance` the `balance` permission
+ */
+export type QueryWithPermit =
+  | {
+    my_games: {
+      limit?: number | null,
+      start_after?: number | null,
+    },
+  }
+  | {
+    my_balance: {},
+  };
+
+/**
+ * How long a game waits at a given stage, counted from when it reached that stage
+ */
+export type Timeout =
+  | {
+    blocks: number,
+  }
+  | {
+    seconds: number,
+  };
+
+/**
+ * A point in time in nanosecond precision.
+ *
+ * This type can represent times from 1970-01-01T00:00:00Z to 2554-07-21T23:34:33Z.
+ *
+ * ## Examples
+ *
+ * ``` # use secret_cosmwasm_std::Timestamp; let ts = Timestamp::from_nanos(1_000_000_202); assert_eq!(ts.nanos(), 1_000_000_202); assert_eq!(ts.seconds(), 1); assert_eq!(ts.subsec_nanos(), 202);
+ *
+ * let ts = ts.plus_seconds(2); assert_eq!(ts.nanos(), 3_000_000_202); assert_eq!(ts.seconds(), 3); assert_eq!(ts.subsec_nanos(), 202); ```
+ */
+export type Timestamp = Uint64;
+
+/**
+ * A SNIP-20 token contract
+ */
+export type TokenContract = {
+  address: Addr,
+  code_hash: string,
+};
+
+export type TokenPermissions =
+  /**
+   * Allowance for SNIP-20 - Permission to query allowance of the owner & spender
+   */
+  | "allowance"
+  /**
+   * Balance for SNIP-20 - Permission to query balance
+   */
+  | "balance"
+  /**
+   * History for SNIP-20 - Permission to query transfer_history & transaction_hisotry
+   */
+  | "history"
+  /**
+   * Owner permission indicates that the bearer of this permit should be granted all the access of the creator/signer of the permit.  SNIP-721 uses this to grant viewing access to all data that the permit creator owns and is whitelisted for. For SNIP-721 use, a permit with Owner permission should NEVER be given to anyone else.  If someone wants to share private data, they should whitelist the address they want to share with via a SetWhitelistedApproval tx, and that address will view the data by creating their own permit with Owner permission
+   */
+  | "owner";
+
+/**
+ * A thin wrapper around u128 that is using strings for JSON encoding/decoding, such that the full u128 range can be used for clients that convert JSON numbers to floats, like JavaScript and jq.
+ *
+ * # Examples
+ *
+ * Use `from` to create instances of this and `u128` to get the value out:
+ *
+ * ``` # use secret_cosmwasm_std::Uint128; let a = Uint128::from(123u128); assert_eq!(a.u128(), 123);
+ *
+ * let b = Uint128::from(42u64); assert_eq!(b.u128(), 42);
+ *
+ * let c = Uint128::from(70u32); assert_eq!(c.u128(), 70); ```
+ */
+export type Uint128 = string;
+
+/**
+ * A thin wrapper around u64 that is using strings for JSON encoding/decoding, such that the full u64 range can be used for clients that convert JSON numbers to floats, like JavaScript and jq.
+ *
+ * # Examples
+ *
+ * Use `from` to create instances of this and `u64` to get the value out:
+ *
+ * ``` # use secret_cosmwasm_std::Uint64; let a = Uint64::from(42u64); assert_eq!(a.u64(), 42);
+ *
+ * let b = Uint64::from(70u32); assert_eq!(b.u64(), 70); ```
+ */
+export type Uint64 = string;
@@ -1,12 +1,8 @@
 import { SecretNetworkClient, Tx, Coin } from "secretjs";
 import fs from "fs";
 import assert from "assert";
-import { getScrtBalance, initClient, } from "./int_helpers";
-import { 
-  Account, ContractInfo, jsEnv, 
-  InitMsg, HandleMsg, JoinMsg, RollDiceMsg, LeaveMsg,
-  QueryMsg, QueryResponse, WhoWonMsg, 
-} from "./int_types";
+import { Account, ContractInfo, jsEnv, getScrtBalance, initClient, } from "./int_helpers";
+import { InstantiateMsg, ExecuteMsg, QueryMsg, WinnerResponse } from "./int_types";
 
 /////////////////////////////////////////////////////////////////////////////////
 // Global variables
@@ -22,7 +18,7 @@ const gasLimit = 200000;
 const initializeContract = async (
   client: SecretNetworkClient,
   contractPath: string,
-  initMsg: InitMsg,
+  initMsg: InstantiateMsg,
 ) => {
   // upload contract
   const wasmCode = fs.readFileSync(contractPath);
@@ -96,7 +92,10 @@ async function initDefault(): Promise<jsEnv> {
   const accounts = await initClient();
   const { secretjs } = accounts[0];
 
-  const initMsgDefault = {  };
+  const initMsgDefault: InstantiateMsg = {
+    stake: { denom: "uscrt", amount: "1000000" },
+    prng_seed: Buffer.from("entropy").toString("base64"),
+  };
   
   const [contractHash, contractAddress] = await initializeContract(
     secretjs,
@@ -126,7 +125,7 @@ async function initDefault(): Promise<jsEnv> {
 async function execHandle(
   sender: Account,
   contract: ContractInfo,
-  msg: HandleMsg,
+  msg: ExecuteMsg,
   handleDescription?: string,
   sendAmount?: number,
 ): Promise<Tx> {
@@ -161,12 +160,14 @@ async function execHandle(
 async function execJoin(
   sender: Account,
   contract: ContractInfo,
+  gameId: number,
   name: string,
   secret: number,
   deposit: number,
 ) {
-  const msg: JoinMsg = {
+  const msg: ExecuteMsg = {
     join: { 
+      game_id: gameId,
       name,
       secret: secret.toString(),
     },
@@ -179,9 +180,10 @@ async function execJoin(
 async function execRollDice(
   sender: Account,
   contract: ContractInfo,
+  gameId: number,
 ) {
-  const msg: RollDiceMsg = {
-    roll_dice: {  },
+  const msg: ExecuteMsg = {
+    roll_dice: { game_id: gameId },
   };
 
   const tx = await execHandle(sender, contract, msg, "Roll Dice");
@@ -191,9 +193,10 @@ async function execRollDice(
 async function execLeave(
   sender: Account,
   contract: ContractInfo,
+  gameId: number,
 ) {
-  const msg: LeaveMsg = {
-    leave: {  },
+  const msg: ExecuteMsg = {
+    leave: { game_id: gameId },
   };
 
   const tx = await execHandle(sender, contract, msg, "Leave");
@@ -204,14 +207,14 @@ async function execLeave(
 // Query Messages
 /////////////////////////////////////////////////////////////////////////////////
 
-async function execQuery(
+async function execQuery<T>(
   sender: Account,
   contract: ContractInfo,
   msg: QueryMsg,
-): Promise<QueryResponse> {
+): Promise<T> {
   const { secretjs } = sender;
 
-  const response: QueryResponse = (await secretjs.query.compute.queryContract({
+  const response: T = (await secretjs.query.compute.queryContract({
     contractAddress: contract.address,
     codeHash: contract.hash,
     query: msg,
@@ -227,11 +230,11 @@ async function execQuery(
 async function queryWhoWon(
   sender: Account,
   contract: ContractInfo,
+  gameId: number,
 ) {
-  const msg: WhoWonMsg = { who_won: {  } };
+  const msg: QueryMsg = { who_won: { game_id: gameId } };
   
-  const response = await execQuery(sender, contract, msg);
-  return response;
+  return await execQuery<WinnerResponse>(sender, contract, msg);
 }
 
 /////////////////////////////////////////////////////////////////////////////////
@@ -245,40 +248,41 @@ async function testSanity(
   const player1 = env.accounts[1];
   const player2 = env.accounts[2];
   const contract = env.contracts[0];
+  const gameId = 1;
 
   let p0BalStart = parseInt(await getScrtBalance(player0));
   let p1BalStart = parseInt(await getScrtBalance(player1));
 
   // players join
-  let txP0Join = await execJoin(player0, contract, "name0", 123, 1_000_000);
+  let txP0Join = await execJoin(player0, contract, gameId, "name0", 123, 1_000_000);
   assert(txP0Join.code === 0);
 
-  let txP1Join = await execJoin(player1, contract, "name1", 321, 1_000_000);
+  let txP1Join = await execJoin(player1, contract, gameId, "name1", 321, 1_000_000);
   assert(txP1Join.code === 0);
 
   // 3rd player cannot join
-  let tx = await execJoin(player2, contract, "name2", 111, 1_000_000);
+  let tx = await execJoin(player2, contract, gameId, "name2", 111, 1_000_000);
   assert(tx.code !== 0 && tx.rawLog.includes('The game is full'));
 
   // Roll dice
-  let txP0Roll = await execRollDice(player0, contract);
+  let txP0Roll = await execRollDice(player0, contract, gameId);
   assert(txP0Roll.code === 0);
 
   await new Promise(f => setTimeout(f, 6000));
 
-  let qRes = await queryWhoWon(player0, contract);
+  let qRes = await queryWhoWon(player0, contract, gameId);
   assert((
-    ((qRes.name === "name0" && qRes.addr === player0.address) || (qRes.name === "name1" && qRes.addr === player1.address))
-    && (qRes.dice_roll >= 0 || qRes.dice_roll <= 6)
+    (qRes.name === "name0" || qRes.name === "name1")
+    && qRes.dice_roll !== null && (qRes.dice_roll >= 1 && qRes.dice_roll <= 6)
   ));
 
   let p0BalEnd = parseInt(await getScrtBalance(player0));
   let p1BalEnd = parseInt(await getScrtBalance(player1));
 
-  if (qRes.addr === player0.address) {
+  if (qRes.name === "name0") {
     assert(p0BalEnd === p0BalStart + 1_000_000 - gasLimit * 2 * 0.1);
     assert(p1BalEnd === p1BalStart - 1_000_000 - gasLimit * 0.1);
-  } else if (qRes.addr === player1.address) {
+  } else if (qRes.name === "name1") {
     assert(p0BalEnd === p0BalStart - 1_000_000 - gasLimit * 2 * 0.1);
     assert(p1BalEnd === p1BalStart + 1_000_000 - gasLimit * 0.1);
   } else {
@@ -286,19 +290,19 @@ async function testSanity(
   }
 
   // cannot leave after game is over
-  tx = await execLeave(player0, contract);
+  tx = await execLeave(player0, contract, gameId);
   assert(tx.code !== 0);
-  tx = await execLeave(player1, contract);
+  tx = await execLeave(player1, contract, gameId);
   assert(tx.code !== 0);
 
   // cannot roll dice again
-  tx = await execRollDice(player0, contract);
+  tx = await execRollDice(player0, contract, gameId);
   assert(tx.code !== 0);
-  tx = await execRollDice(player1, contract);
+  tx = await execRollDice(player1, contract, gameId);
   assert(tx.code !== 0);
 
   // cannot join once game is over
-  tx = await execJoin(player0, contract, "test", 1024, 1_000_000);
+  tx = await execJoin(player0, contract, gameId, "test", 1024, 1_000_000);
   assert(tx.code !== 0 && tx.rawLog.includes('The game is already over'));
 
   // check contract has 0 scrt balance
@@ -312,23 +316,24 @@ async function testMustDeposit(
   const player0 = env.accounts[0];
   const player1 = env.accounts[1];
   const contract = env.contracts[0];
+  const gameId = 1;
 
-  let tx = await execJoin(player0, contract, "name0", 123, 0);
+  let tx = await execJoin(player0, contract, gameId, "name0", 123, 0);
   assert(tx.code !== 0);
-  tx = await execJoin(player0, contract, "name0", 123, 500);
+  tx = await execJoin(player0, contract, gameId, "name0", 123, 500);
   assert(tx.code !== 0);
-  tx = await execJoin(player0, contract, "name0", 123, 5_000_000);
+  tx = await execJoin(player0, contract, gameId, "name0", 123, 5_000_000);
   assert(tx.code !== 0);
 
   // player0 deposits correctly
-  tx = await execJoin(player0, contract, "name0", 123, 1_000_000);
+  tx = await execJoin(player0, contract, gameId, "name0", 123, 1_000_000);
   assert(tx.code === 0);
 
-  tx = await execJoin(player1, contract, "name0", 123, 0);
+  tx = await execJoin(player1, contract, gameId, "name0", 123, 0);
   assert(tx.code !== 0);
-  tx = await execJoin(player1, contract, "name0", 123, 500);
+  tx = await execJoin(player1, contract, gameId, "name0", 123, 500);
   assert(tx.code !== 0);
-  tx = await execJoin(player1, contract, "name0", 123, 5_000_000);
+  tx = await execJoin(player1, contract, gameId, "name0", 123, 5_000_000);
   assert(tx.code !== 0);
 
   const contractBal = await getScrtBalance(player0, contract.address);
@@ -341,28 +346,29 @@ async function testLeave(
   const player0 = env.accounts[0];
   const player1 = env.accounts[1];
   const contract = env.contracts[0];
+  const gameId = 1;
 
   let p0BalStart = parseInt(await getScrtBalance(player0));
 
   // player0 joins then leave
-  let tx = await execJoin(player0, contract, "name0", 123, 1_000_000);
+  let tx = await execJoin(player0, contract, gameId, "name0", 123, 1_000_000);
   assert(tx.code === 0);
-  tx = await execLeave(player0, contract);
+  tx = await execLeave(player0, contract, gameId);
   assert(tx.code === 0);
   assert(parseInt(await getScrtBalance(player0)) === p0BalStart - gasLimit * 2 * 0.1);
   
   // player0 joins...
-  tx = await execJoin(player0, contract, "name00", 1234, 1_000_000);
+  tx = await execJoin(player0, contract, gameId, "name00", 1234, 1_000_000);
   assert(tx.code === 0);
 
   // cannot roll dice yet
-  tx = await execRollDice(player0, contract);
+  tx = await execRollDice(player0, contract, gameId);
   assert(tx.code !== 0);
   
   // ...then player1 joins, but cannot leave! Hotel California
-  tx = await execJoin(player1, contract, "name1", 1234, 1_000_000);
+  tx = await execJoin(player1, contract, gameId, "name1", 1234, 1_000_000);
   assert(tx.code === 0);
-  tx = await execLeave(player1, contract);
+  tx = await execLeave(player1, contract, gameId);
   assert(tx.code !== 0);
 
   // contract has 2 scrt 
@@ -376,10 +382,11 @@ async function testP1CanJoinTwice(
 ) {
   const player0 = env.accounts[0];
   const contract = env.contracts[0];
+  const gameId = 1;
 
-  let tx = await execJoin(player0, contract, "name0", 123, 1_000_000);
+  let tx = await execJoin(player0, contract, gameId, "name0", 123, 1_000_000);
   assert(tx.code === 0);
-  tx = await execJoin(player0, contract, "name0", 123, 1_000_000);
+  tx = await execJoin(player0, contract, gameId, "name0", 123, 1_000_000);
   assert(tx.code === 0);
 }
 
@@ -0,0 +1,247 @@
+//! A small stand-in for a chain: the contract, a bank module holding every account's coins, other
+//! contracts it can call, and blocks. Messages are atomic, as on chain, so a failed execute leaves
+//! balances and storage as they were
+
+use std::collections::BTreeMap;
+
+use cosmwasm_std::testing::{mock_env, MockApi, MockQuerier};
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, ContractInfoResponse, ContractResult,
+    CosmosMsg, Deps, DepsMut, Env, MessageInfo, QuerierWrapper, Reply, ReplyOn, Response,
+    StdResult, Storage, SubMsg, SubMsgResult, SystemError, SystemResult, Timestamp, Uint128,
+    WasmMsg, WasmQuery,
+};
+use serde::de::DeserializeOwned;
+
+use secret_dice_contract_pathway::contract::{execute, instantiate, query, reply};
+use secret_dice_contract_pathway::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+
+/// Seconds between two blocks
+const BLOCK_TIME: u64 = 6;
+
+/// In-memory contract storage that can be copied, so that failed messages can be rolled back
+#[derive(Clone, Default)]
+struct AppStorage(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl Storage for AppStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.0.remove(key);
+    }
+}
+
+/// Coins held by each account, by denom
+#[derive(Clone, Default)]
+struct Bank(BTreeMap<(Addr, String), Uint128>);
+
+impl Bank {
+    fn balance(&self, addr: &Addr, denom: &str) -> Uint128 {
+        self.0.get(&(addr.clone(), denom.to_string())).copied().unwrap_or_default()
+    }
+
+    fn mint(&mut self, addr: &Addr, coin: &Coin) {
+        let balance = self.balance(addr, &coin.denom) + coin.amount;
+        self.0.insert((addr.clone(), coin.denom.clone()), balance);
+    }
+
+    fn send(&mut self, from: &Addr, to: &Addr, coins: &[Coin]) -> Result<(), String> {
+        for coin in coins {
+            let balance = self.balance(from, &coin.denom);
+            let remaining = balance.checked_sub(coin.amount).map_err(|_| {
+                format!("{} has {}{} and can't send {}", from, balance, coin.denom, coin)
+            })?;
+            self.0.insert((from.clone(), coin.denom.clone()), remaining);
+            self.mint(to, coin);
+        }
+
+        Ok(())
+    }
+}
+
+/// Another contract, reduced to accepting or rejecting the messages it is sent
+pub type MockContract = fn(&Binary) -> Result<(), String>;
+
+pub struct App {
+    contract: Addr,
+    /// The other contracts, and the messages they accepted
+    contracts: BTreeMap<Addr, MockContract>,
+    inbox: Vec<(Addr, Binary)>,
+    storage: AppStorage,
+    bank: Bank,
+    api: MockApi,
+    querier: MockQuerier,
+    height: u64,
+    time: Timestamp,
+}
+
+impl App {
+    /// Instantiates the contract at the address `contract`
+    pub fn new(admin: &str, msg: InstantiateMsg) -> Result<App, String> {
+        let env = mock_env();
+        let mut app = App {
+            contract: Addr::unchecked("contract"),
+            contracts: BTreeMap::new(),
+            inbox: vec![],
+            storage: AppStorage::default(),
+            bank: Bank::default(),
+            api: MockApi::default(),
+            querier: MockQuerier::new(&[]),
+            height: env.block.height,
+            time: env.block.time,
+        };
+
+        let env = app.env();
+        let info = MessageInfo { sender: Addr::unchecked(admin), funds: vec![] };
+        let res = instantiate(app.deps_mut(), env, info, msg).map_err(|err| err.to_string())?;
+        app.settle(res)?;
+
+        Ok(app)
+    }
+
+    pub fn contract(&self) -> &Addr {
+        &self.contract
+    }
+
+    /// Gives `coins` to `addr` out of thin air
+    pub fn fund(&mut self, addr: &str, coins: &[Coin]) {
+        for coin in coins {
+            self.bank.mint(&Addr::unchecked(addr), coin);
+        }
+    }
+
+    /// Adds a contract at `addr`, which the contract can then tell apart from an account
+    pub fn add_contract(&mut self, addr: &str, contract: MockContract) {
+        self.contracts.insert(Addr::unchecked(addr), contract);
+
+        let addrs: Vec<String> = self.contracts.keys().map(|addr| addr.to_string()).collect();
+        self.querier.update_wasm(move |query| match query {
+            WasmQuery::ContractInfo { contract_addr } if addrs.contains(contract_addr) => SystemResult::Ok(
+                ContractResult::Ok(to_binary(&ContractInfoResponse::new(1, "admin")).unwrap()),
+            ),
+            _ => SystemResult::Err(SystemError::NoSuchContract { addr: String::new() }),
+        });
+    }
+
+    /// The messages the contract at `addr` accepted, oldest first
+    pub fn received(&self, addr: &str) -> Vec<Binary> {
+        self.inbox
+            .iter()
+            .filter(|(to, _)| to.as_str() == addr)
+            .map(|(_, msg)| msg.clone())
+            .collect()
+    }
+
+    pub fn balance(&self, addr: &str, denom: &str) -> u128 {
+        self.bank.balance(&Addr::unchecked(addr), denom).u128()
+    }
+
+    pub fn next_block(&mut self) {
+        self.height += 1;
+        self.time = self.time.plus_seconds(BLOCK_TIME);
+    }
+
+    /// Runs `msg` from `sender` with `funds` attached, then the bank messages it returns. Nothing
+    /// is kept if any of it fails
+    pub fn execute(&mut self, sender: &str, msg: ExecuteMsg, funds: &[Coin]) -> Result<Response, String> {
+        let storage = self.storage.clone();
+        let bank = self.bank.clone();
+        let inbox = self.inbox.len();
+
+        let res = self.try_execute(sender, msg, funds);
+        if res.is_err() {
+            self.storage = storage;
+            self.bank = bank;
+            self.inbox.truncate(inbox);
+        }
+
+        res
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: QueryMsg) -> StdResult<T> {
+        let deps = Deps {
+            storage: &self.storage,
+            api: &self.api,
+            querier: QuerierWrapper::new(&self.querier),
+        };
+        from_binary(&query(deps, self.env(), msg)?)
+    }
+
+    fn try_execute(&mut self, sender: &str, msg: ExecuteMsg, funds: &[Coin]) -> Result<Response, String> {
+        let sender = Addr::unchecked(sender);
+        self.bank.send(&sender, &self.contract.clone(), funds)?;
+
+        let env = self.env();
+        let info = MessageInfo { sender, funds: funds.to_vec() };
+        let res = execute(self.deps_mut(), env, info, msg).map_err(|err| err.to_string())?;
+        self.settle(res)
+    }
+
+    /// Delivers the messages of a response. A failed submessage is undone and, if the contract
+    /// asked for it, replied to instead of failing the whole execute
+    fn settle(&mut self, res: Response) -> Result<Response, String> {
+        for sub_msg in &res.messages {
+            let bank = self.bank.clone();
+            let err = match self.deliver(sub_msg) {
+                Ok(()) => continue,
+                Err(err) => err,
+            };
+            self.bank = bank;
+
+            match sub_msg.reply_on {
+                ReplyOn::Error => {
+                    let env = self.env();
+                    let msg = Reply { id: sub_msg.id, result: SubMsgResult::Err(err) };
+                    let res = reply(self.deps_mut(), env, msg).map_err(|err| err.to_string())?;
+                    self.settle(res)?;
+                },
+                ReplyOn::Never => return Err(err),
+                _ => return Err(format!("Unsupported reply: {:?}", sub_msg.reply_on)),
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn deliver(&mut self, sub_msg: &SubMsg) -> Result<(), String> {
+        match &sub_msg.msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                self.bank.send(&self.contract.clone(), &Addr::unchecked(to_address), amount)
+            },
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, funds, .. }) => {
+                let to = Addr::unchecked(contract_addr);
+                let contract = self.contracts
+                    .get(&to)
+                    .ok_or_else(|| format!("No contract at {}", to))?;
+                contract(msg)?;
+
+                self.bank.send(&self.contract.clone(), &to, funds)?;
+                self.inbox.push((to, msg.clone()));
+                Ok(())
+            },
+            msg => Err(format!("Unsupported message: {:?}", msg)),
+        }
+    }
+
+    fn deps_mut(&mut self) -> DepsMut<'_> {
+        DepsMut {
+            storage: &mut self.storage,
+            api: &self.api,
+            querier: QuerierWrapper::new(&self.querier),
+        }
+    }
+
+    fn env(&self) -> Env {
+        let mut env = mock_env();
+        env.block.height = self.height;
+        env.block.time = self.time;
+        env.contract.address = self.contract.clone();
+        env
+    }
+}
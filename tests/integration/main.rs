@@ -0,0 +1,286 @@
+//! End-to-end flows against a simulated bank, checking where the coins actually end up
+
+mod app;
+mod properties;
+
+use cosmwasm_std::{coins, from_binary, Addr, Binary, Coin, Uint128};
+
+use secret_dice_contract_pathway::msg::{
+    BankrollResponse, BetResponse, CallbackMsg, ExecuteAnswer, ExecuteMsg, FeesResponse, InstantiateMsg, Prediction, QueryMsg,
+    WinnerResponse,
+};
+use secret_dice_contract_pathway::state::{Callback, ContractStatus};
+
+use app::App;
+
+const STAKE: u128 = 1_000_000;
+const FUNDS: u128 = 10_000_000;
+
+fn instantiate_msg(fee_bps: u16) -> InstantiateMsg {
+    InstantiateMsg {
+        stake: Coin::new(STAKE, "uscrt"),
+        token: None,
+        commit_reveal: None,
+        join_timeout: None,
+        roll_timeout: None,
+        prng_seed: Some(Binary::from(b"entropy")),
+        admin: None,
+        fee_bps: Some(fee_bps),
+        house_edge_bps: None,
+        max_exposure_bps: None,
+    }
+}
+
+/// A contract administered by `admin`, and players funded with 10 SCRT each
+fn setup(fee_bps: u16, players: &[&str]) -> App {
+    let mut app = App::new("admin", instantiate_msg(fee_bps)).unwrap();
+    for player in players {
+        app.fund(player, &coins(FUNDS, "uscrt"));
+    }
+    app
+}
+
+fn join(app: &mut App, game_id: u64, player: &str, seats: Option<u8>, secret: u128) -> Result<(), String> {
+    let msg = ExecuteMsg::Join {
+        game_id,
+        name: player.to_string(),
+        seats,
+        dice: None,
+        secret: Some(Uint128::new(secret)),
+        commitment: None,
+        callback: None,
+    };
+    app.execute(player, msg, &coins(STAKE, "uscrt")).map(|_| ())
+}
+
+/// Places a bet for `player` with the stake, and gives its id
+fn place_bet(app: &mut App, player: &str, msg: ExecuteMsg) -> u64 {
+    let res = app.execute(player, msg, &coins(STAKE, "uscrt")).unwrap();
+    match from_binary(&res.data.unwrap()).unwrap() {
+        ExecuteAnswer::Bet { bet_id } => bet_id,
+        answer => panic!("unexpected answer: {:?}", answer),
+    }
+}
+
+#[test]
+fn the_winner_is_paid_the_pot_minus_the_fee() {
+    let mut app = setup(250, &["alice", "bob"]);
+
+    join(&mut app, 1, "alice", None, 1234).unwrap();
+    join(&mut app, 1, "bob", None, 5678).unwrap();
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 2 * STAKE);
+
+    app.execute("alice", ExecuteMsg::RollDice { game_id: 1 }, &[]).unwrap();
+    app.next_block();
+    let winner: WinnerResponse = app.query(QueryMsg::WhoWon { game_id: 1 }).unwrap();
+    let loser = if winner.name == "alice" { "bob" } else { "alice" };
+
+    // 2.5% of the 2 SCRT pot stays with the contract
+    assert_eq!(app.balance(&winner.name, "uscrt"), FUNDS + STAKE - 50_000);
+    assert_eq!(app.balance(loser, "uscrt"), FUNDS - STAKE);
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 50_000);
+
+    let fees: FeesResponse = app.query(QueryMsg::Fees {}).unwrap();
+    assert_eq!(fees.accrued, Coin::new(50_000, "uscrt"));
+    let msg = ExecuteMsg::WithdrawFees { recipient: "admin".to_string(), amount: Uint128::new(50_000) };
+    app.execute("admin", msg, &[]).unwrap();
+    assert_eq!(app.balance("admin", "uscrt"), 50_000);
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
+
+#[test]
+fn every_player_pays_into_a_bigger_pot() {
+    let mut app = setup(0, &["alice", "bob", "carol"]);
+
+    join(&mut app, 1, "alice", Some(3), 1).unwrap();
+    join(&mut app, 1, "bob", None, 2).unwrap();
+    join(&mut app, 1, "carol", None, 3).unwrap();
+    app.execute("carol", ExecuteMsg::RollDice { game_id: 1 }, &[]).unwrap();
+    app.next_block();
+
+    let winner: WinnerResponse = app.query(QueryMsg::WhoWon { game_id: 1 }).unwrap();
+    for player in ["alice", "bob", "carol"] {
+        let expected = if player == winner.name { FUNDS + 2 * STAKE } else { FUNDS - STAKE };
+        assert_eq!(app.balance(player, "uscrt"), expected);
+    }
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
+
+#[test]
+fn leaving_and_cancelling_refund_the_deposits() {
+    let mut app = setup(250, &["alice", "bob"]);
+
+    join(&mut app, 1, "alice", None, 1234).unwrap();
+    assert_eq!(app.balance("alice", "uscrt"), FUNDS - STAKE);
+    app.execute("alice", ExecuteMsg::Leave { game_id: 1 }, &[]).unwrap();
+    assert_eq!(app.balance("alice", "uscrt"), FUNDS);
+
+    // no fee is kept from a cancelled game
+    join(&mut app, 2, "alice", None, 1234).unwrap();
+    join(&mut app, 2, "bob", None, 5678).unwrap();
+    app.execute("alice", ExecuteMsg::Cancel { game_id: 2 }, &[]).unwrap();
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 2 * STAKE);
+    app.execute("bob", ExecuteMsg::Cancel { game_id: 2 }, &[]).unwrap();
+
+    assert_eq!(app.balance("alice", "uscrt"), FUNDS);
+    assert_eq!(app.balance("bob", "uscrt"), FUNDS);
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
+
+#[test]
+fn rejected_messages_keep_the_coins() {
+    let mut app = setup(0, &["alice", "bob", "carol"]);
+
+    // the wrong deposit goes back to the sender
+    let msg = ExecuteMsg::Join {
+        game_id: 1,
+        name: "alice".to_string(),
+        seats: None,
+        dice: None,
+        secret: Some(Uint128::new(1234)),
+        commitment: None,
+        callback: None,
+    };
+    let err = app.execute("alice", msg, &coins(2 * STAKE, "uscrt")).unwrap_err();
+    assert_eq!(err, "Must deposit 1000000uscrt to play.");
+    assert_eq!(app.balance("alice", "uscrt"), FUNDS);
+
+    // so does a deposit for a game that is already full
+    join(&mut app, 1, "alice", None, 1234).unwrap();
+    join(&mut app, 1, "bob", None, 5678).unwrap();
+    let err = join(&mut app, 1, "carol", None, 42).unwrap_err();
+    assert_eq!(err, "The game is full.");
+    assert_eq!(app.balance("carol", "uscrt"), FUNDS);
+
+    // and no one can play with coins they don't have
+    let err = join(&mut app, 2, "dave", None, 42).unwrap_err();
+    assert!(err.contains("can't send"));
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 2 * STAKE);
+}
+
+#[test]
+fn bets_are_paid_from_the_bankroll() {
+    let mut app = setup(0, &["alice"]);
+    app.fund("admin", &coins(100_000_000, "uscrt"));
+    app.execute("admin", ExecuteMsg::DepositBankroll {}, &coins(100_000_000, "uscrt")).unwrap();
+
+    // an odd number of even-money bets can't leave alice's balance where it started
+    for secret in 0..21 {
+        let msg = ExecuteMsg::Bet { prediction: Prediction::Over(3), dice: None, secret: Uint128::new(secret) };
+        let bet_id = place_bet(&mut app, "alice", msg);
+
+        // until the bet is settled, its payout is kept aside from the bankroll
+        let bankroll: BankrollResponse = app.query(QueryMsg::Bankroll {}).unwrap();
+        let bet: BetResponse = app.query(QueryMsg::Bet { bet_id }).unwrap();
+        assert_eq!(
+            app.balance(app.contract().as_str(), "uscrt"),
+            bankroll.balance.amount.u128() + bet.payout.amount.u128(),
+        );
+
+        // whatever alice wins or loses, the contract then holds exactly the bankroll
+        app.next_block();
+        app.execute("alice", ExecuteMsg::SettleBet { bet_id }, &[]).unwrap();
+        let bankroll: BankrollResponse = app.query(QueryMsg::Bankroll {}).unwrap();
+        assert_eq!(app.balance(app.contract().as_str(), "uscrt"), bankroll.balance.amount.u128());
+        assert_eq!(app.balance("alice", "uscrt") + bankroll.balance.amount.u128(), FUNDS + 100_000_000);
+    }
+    assert_ne!(app.balance("alice", "uscrt"), FUNDS);
+
+    // the admin gets back what is left
+    let left = app.balance(app.contract().as_str(), "uscrt");
+    let msg = ExecuteMsg::WithdrawBankroll { recipient: "admin".to_string(), amount: Uint128::new(left) };
+    app.execute("admin", msg, &[]).unwrap();
+    assert_eq!(app.balance("admin", "uscrt"), left);
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
+
+#[test]
+fn contracts_cannot_take_back_a_losing_bet() {
+    let mut app = setup(0, &["alice", "gambler"]);
+    app.add_contract("gambler", |_| Ok(()));
+    app.fund("admin", &coins(100_000_000, "uscrt"));
+    app.execute("admin", ExecuteMsg::DepositBankroll {}, &coins(100_000_000, "uscrt")).unwrap();
+
+    // a contract could look at the outcome of its bet and revert it if it lost, but neither placing
+    // the bet nor settling it in the same block tells the outcome
+    let msg = ExecuteMsg::Bet { prediction: Prediction::Over(3), dice: None, secret: Uint128::new(1) };
+    let bet_id = place_bet(&mut app, "gambler", msg);
+    let err = app.execute("alice", ExecuteMsg::SettleBet { bet_id }, &[]).unwrap_err();
+    assert_eq!(err, "A bet can only be settled in a later block than the one it was placed in.");
+
+    // and only accounts can settle it afterwards
+    app.next_block();
+    let err = app.execute("gambler", ExecuteMsg::SettleBet { bet_id }, &[]).unwrap_err();
+    assert_eq!(err, "Bets can't be settled by contracts.");
+    app.execute("alice", ExecuteMsg::SettleBet { bet_id }, &[]).unwrap();
+    let bet: BetResponse = app.query(QueryMsg::Bet { bet_id }).unwrap();
+    assert_eq!(bet.outcome, None);
+
+    // once it is known, the outcome stands, and the coins are where it says
+    app.next_block();
+    let bet: BetResponse = app.query(QueryMsg::Bet { bet_id }).unwrap();
+    let won = bet.outcome.unwrap().won;
+    let expected = if won { FUNDS - STAKE + bet.payout.amount.u128() } else { FUNDS - STAKE };
+    assert_eq!(app.balance("gambler", "uscrt"), expected);
+    let bankroll: BankrollResponse = app.query(QueryMsg::Bankroll {}).unwrap();
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), bankroll.balance.amount.u128());
+}
+
+#[test]
+fn emergency_refund_pays_everyone_back() {
+    let mut app = setup(0, &["alice", "bob", "carol"]);
+
+    join(&mut app, 1, "alice", None, 1234).unwrap();
+    join(&mut app, 2, "bob", None, 5678).unwrap();
+    join(&mut app, 2, "carol", None, 42).unwrap();
+
+    app.execute("admin", ExecuteMsg::SetStatus { status: ContractStatus::StopAll }, &[]).unwrap();
+    app.execute("admin", ExecuteMsg::EmergencyRefund { limit: None }, &[]).unwrap();
+
+    for player in ["alice", "bob", "carol"] {
+        assert_eq!(app.balance(player, "uscrt"), FUNDS);
+    }
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
+
+#[test]
+fn callbacks_cannot_hold_up_the_payout() {
+    let mut app = setup(0, &["alice", "bob"]);
+    app.add_contract("tournament", |_| Ok(()));
+    app.add_contract("broken", |_| Err("out of gas".to_string()));
+
+    for (game_id, callback) in [(1, "tournament"), (2, "broken")] {
+        let msg = ExecuteMsg::Join {
+            game_id,
+            name: "alice".to_string(),
+            seats: None,
+            dice: None,
+            secret: Some(Uint128::new(1234)),
+            commitment: None,
+            callback: Some(Callback {
+                contract_addr: Addr::unchecked(callback),
+                code_hash: format!("{}_hash", callback),
+                msg: None,
+            }),
+        };
+        app.execute("alice", msg, &coins(STAKE, "uscrt")).unwrap();
+        join(&mut app, game_id, "bob", None, 5678).unwrap();
+        app.execute("bob", ExecuteMsg::RollDice { game_id }, &[]).unwrap();
+        app.next_block();
+    }
+
+    let received = app.received("tournament");
+    assert_eq!(received.len(), 1);
+    let winner: WinnerResponse = app.query(QueryMsg::WhoWon { game_id: 1 }).unwrap();
+    assert_eq!(from_binary::<CallbackMsg>(&received[0]).unwrap(), CallbackMsg::GameFinished {
+        game_id: 1,
+        winner: Addr::unchecked(winner.name),
+        dice_roll: winner.dice_roll,
+        msg: None,
+    });
+
+    // both pots were paid out, whether the callback went through or not
+    assert!(app.received("broken").is_empty());
+    assert_eq!(app.balance("alice", "uscrt") + app.balance("bob", "uscrt"), 2 * FUNDS);
+    assert_eq!(app.balance(app.contract().as_str(), "uscrt"), 0);
+}
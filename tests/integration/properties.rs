@@ -0,0 +1,172 @@
+//! Random sequences of calls from random senders, checking after each one that the coins and the
+//! game states stay consistent
+
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{coins, Uint128};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use secret_dice_contract_pathway::msg::{
+    ExecuteMsg, FeesResponse, GameStatusResponse, QueryMsg, WinnerResponse,
+};
+use secret_dice_contract_pathway::state::ContractState;
+
+use crate::app::App;
+use crate::{instantiate_msg, STAKE};
+
+const PLAYERS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+const GAMES: u64 = 3;
+const FEE_BPS: u16 = 250;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Join { game_id: u64, player: usize, seats: Option<u8>, funds: u128 },
+    RollDice { game_id: u64, player: usize },
+    Leave { game_id: u64, player: usize },
+    WhoWon { game_id: u64 },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let game_id = 0..GAMES;
+    let player = 0..PLAYERS.len();
+    let funds = prop_oneof![4 => Just(STAKE), 1 => Just(0), 1 => Just(STAKE - 1), 1 => Just(2 * STAKE)];
+    let seats = prop_oneof![Just(None), (1u8..5).prop_map(Some)];
+
+    prop_oneof![
+        3 => (game_id.clone(), player.clone(), seats, funds)
+            .prop_map(|(game_id, player, seats, funds)| Op::Join { game_id, player, seats, funds }),
+        2 => (game_id.clone(), player.clone()).prop_map(|(game_id, player)| Op::RollDice { game_id, player }),
+        1 => (game_id.clone(), player).prop_map(|(game_id, player)| Op::Leave { game_id, player }),
+        1 => game_id.prop_map(|game_id| Op::WhoWon { game_id }),
+    ]
+}
+
+fn status(app: &App, game_id: u64) -> GameStatusResponse {
+    app.query(QueryMsg::GameStatus { game_id }).unwrap()
+}
+
+fn accrued_fees(app: &App) -> u128 {
+    app.query::<FeesResponse>(QueryMsg::Fees {}).unwrap().accrued.amount.u128()
+}
+
+fn balances(app: &App) -> BTreeMap<&'static str, u128> {
+    PLAYERS.iter().map(|player| (*player, app.balance(player, "uscrt"))).collect()
+}
+
+fn is_player(status: &GameStatusResponse, player: &str) -> bool {
+    status.players.iter().any(|p| p.addr.as_str() == player)
+}
+
+/// The edges a game can move along in a single call. A game that empties goes back to `Init`
+fn is_legal(from: &ContractState, to: &ContractState) -> bool {
+    use ContractState::*;
+
+    from == to || matches!(
+        (from, to),
+        (Init, Open) | (Open, Full) | (Open, Init) | (Full, Done)
+    )
+}
+
+/// Runs `op`, and checks that it was allowed and that exactly the coins it should move did
+fn check_op(app: &mut App, op: &Op) -> Result<(), TestCaseError> {
+    let game_id = match op {
+        Op::Join { game_id, .. } | Op::RollDice { game_id, .. }
+        | Op::Leave { game_id, .. } | Op::WhoWon { game_id } => *game_id,
+    };
+    let before = status(app, game_id);
+    let balances_before = balances(app);
+    let fees_before = accrued_fees(app);
+
+    // every call happens in a block of its own, so the winner can be queried right after
+    let result = match op {
+        Op::Join { player, seats, funds, .. } => {
+            let msg = ExecuteMsg::Join {
+                game_id,
+                name: PLAYERS[*player].to_string(),
+                seats: *seats,
+                dice: None,
+                secret: Some(Uint128::new(*player as u128 + game_id as u128)),
+                commitment: None,
+                callback: None,
+            };
+            app.execute(PLAYERS[*player], msg, &coins(*funds, "uscrt")).map(|_| ())
+        },
+        Op::RollDice { player, .. } => {
+            app.execute(PLAYERS[*player], ExecuteMsg::RollDice { game_id }, &[]).map(|_| ())
+        },
+        Op::Leave { player, .. } => {
+            app.execute(PLAYERS[*player], ExecuteMsg::Leave { game_id }, &[]).map(|_| ())
+        },
+        Op::WhoWon { .. } => {
+            let winner = app.query::<WinnerResponse>(QueryMsg::WhoWon { game_id });
+            prop_assert_eq!(winner.is_ok(), before.state == ContractState::Done);
+            Ok(())
+        },
+    };
+    app.next_block();
+
+    let after = status(app, game_id);
+    prop_assert!(is_legal(&before.state, &after.state), "{:?} -> {:?}", before.state, after.state);
+
+    // what each player is expected to get, or to pay
+    let mut expected = balances_before.clone();
+    match (op, &result) {
+        (_, Err(_)) | (Op::WhoWon { .. }, _) => prop_assert_eq!(&after, &before),
+        (Op::Join { player, funds, .. }, Ok(())) => {
+            prop_assert_eq!(*funds, STAKE);
+            prop_assert!(!is_player(&before, PLAYERS[*player]));
+            prop_assert!(is_player(&after, PLAYERS[*player]));
+            *expected.get_mut(PLAYERS[*player]).unwrap() -= STAKE;
+        },
+        (Op::Leave { player, .. }, Ok(())) => {
+            prop_assert_eq!(&before.state, &ContractState::Open);
+            prop_assert!(is_player(&before, PLAYERS[*player]));
+            *expected.get_mut(PLAYERS[*player]).unwrap() += STAKE;
+        },
+        (Op::RollDice { player, .. }, Ok(())) => {
+            prop_assert_eq!(&before.state, &ContractState::Full);
+            prop_assert!(is_player(&before, PLAYERS[*player]));
+            prop_assert_eq!(&after.state, &ContractState::Done);
+
+            let pot = STAKE * before.players.len() as u128;
+            let fee = accrued_fees(app) - fees_before;
+            prop_assert_eq!(fee, pot * FEE_BPS as u128 / 10_000);
+
+            let winner: WinnerResponse = app.query(QueryMsg::WhoWon { game_id }).unwrap();
+            // players join with their address as their name
+            prop_assert!(is_player(&before, &winner.name));
+            *expected.get_mut(winner.name.as_str()).unwrap() += pot - fee;
+        },
+    }
+    if result.is_err() {
+        prop_assert_eq!(accrued_fees(app), fees_before);
+    }
+    prop_assert_eq!(balances(app), expected);
+
+    // the contract holds the deposits of the unfinished games and the fees, nothing more or less
+    let deposits: u128 = (0..GAMES)
+        .map(|game_id| status(app, game_id))
+        .filter(|status| status.state != ContractState::Done)
+        .map(|status| STAKE * status.players.len() as u128)
+        .sum();
+    prop_assert_eq!(app.balance(app.contract().as_str(), "uscrt"), deposits + accrued_fees(app));
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn coins_and_states_stay_consistent(ops in vec(op(), 1..60)) {
+        let mut app = App::new("admin", instantiate_msg(FEE_BPS)).unwrap();
+        for player in PLAYERS {
+            app.fund(player, &coins(100 * STAKE, "uscrt"));
+        }
+
+        for op in &ops {
+            check_op(&mut app, op)?;
+        }
+    }
+}